@@ -1,6 +1,5 @@
 use std::{
     cmp::{max, min},
-    collections::HashSet,
     fs::read_to_string,
 };
 
@@ -34,169 +33,393 @@ fn parse(text: String) -> Vec<RockStructure> {
     structures
 }
 
-fn check_for_collision(
-    position: (i32, i32),
-    rocks: &Vec<RockStructure>,
-    stopped_sand: &HashSet<(i32, i32)>,
-) -> bool {
-    // Checks if the given position collides with any of the segments of the rock structures
-    // Returns true if there is a collision, false otherwise
+const SOURCE: (i32, i32) = (500, 0);
 
-    // Check for collisions with the stopped sand
-    if stopped_sand.contains(&position) {
-        return true;
-    }
+/// An inclusive `(x_min, y_min, x_max, y_max)` rectangle of the cave.
+type Bounds = (i32, i32, i32, i32);
 
-    for rock in rocks {
-        for i in 0..rock.coords.len() - 1 {
-            let (x1, y1) = rock.coords[i];
-            let (x2, y2) = rock.coords[i + 1];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Air,
+    Rock,
+    Sand,
+}
 
-            if x1 == x2 {
-                // Vertical segment
-                if x1 == position.0 && position.1 >= min(y1, y2) && position.1 <= max(y1, y2) {
-                    return true;
-                }
-            } else {
-                // Horizontal segment
-                if y1 == position.1 && position.0 >= min(x1, x2) && position.0 <= max(x1, x2) {
-                    return true;
+/// What is below the lowest rock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FloorMode {
+    /// Nothing: sand falling past the lowest rock is lost (part 1)
+    Abyss,
+    /// An endless floor this many rows below the lowest rock (part 2)
+    Floor(i32),
+}
+
+/// The cave as a dense grid, wide enough for sand piling up from the source
+/// all the way down to the bottom row.
+#[derive(Clone)]
+struct Cave {
+    cells: Vec<Cell>,
+    x_min: i32,
+    width: i32,
+    /// The floor, or for the abyss the first row below the lowest rock
+    bottom: i32,
+    mode: FloorMode,
+}
+
+impl Cave {
+    fn new(rocks: &[RockStructure], mode: FloorMode) -> Cave {
+        let points = || rocks.iter().flat_map(|r| r.coords.iter());
+        let lowest = points().map(|c| c.1).max().unwrap();
+        let bottom = match mode {
+            FloorMode::Abyss => lowest + 1,
+            FloorMode::Floor(offset) => {
+                assert!(offset > 0, "The floor has to be below the lowest rock");
+                lowest + offset
+            }
+        };
+
+        // Sand can't spread further sideways than it falls
+        let x_min = min(points().map(|c| c.0).min().unwrap(), SOURCE.0 - bottom) - 1;
+        let x_max = max(points().map(|c| c.0).max().unwrap(), SOURCE.0 + bottom) + 1;
+        let width = x_max - x_min + 1;
+
+        let mut cave = Cave {
+            cells: vec![Cell::Air; (width * (bottom + 1)) as usize],
+            x_min,
+            width,
+            bottom,
+            mode,
+        };
+
+        for rock in rocks {
+            for segment in rock.coords.windows(2) {
+                let ((x1, y1), (x2, y2)) = (segment[0], segment[1]);
+                for x in min(x1, x2)..=max(x1, x2) {
+                    for y in min(y1, y2)..=max(y1, y2) {
+                        cave.set((x, y), Cell::Rock);
+                    }
                 }
             }
         }
+        if let FloorMode::Floor(_) = mode {
+            for x in x_min..=x_max {
+                cave.set((x, bottom), Cell::Rock);
+            }
+        }
+
+        cave
     }
 
-    false
-}
+    fn index(&self, (x, y): (i32, i32)) -> usize {
+        (y * self.width + x - self.x_min) as usize
+    }
+
+    fn get(&self, pos: (i32, i32)) -> Cell {
+        self.cells[self.index(pos)]
+    }
 
-fn get_next_collision_point_for_grain(
-    initial_position: (i32, i32),
-    rocks: &Vec<RockStructure>,
-    stopped_sand: &HashSet<(i32, i32)>,
-    lower_bound: i32,
-) -> Option<(i32, i32)> {
-    // Simulates the falling sand from the given position
-    // Returns the final position of the sand
-
-    let mut pos = initial_position;
-
-    loop {
-        // Check if the sand has reached the bottom
-        // (Part 1 )
-        // if pos.1 >= lowest_horizontal_segment_height {
-        //     return None;
-        // }
-        if pos.1 >= lower_bound {
-            return Some(pos);
+    fn set(&mut self, pos: (i32, i32), cell: Cell) {
+        let i = self.index(pos);
+        self.cells[i] = cell;
+    }
+
+    /// Pours sand from the source until it's blocked or a grain falls into the
+    /// abyss, returning the number of grains that came to rest.
+    fn fill(&mut self) -> usize {
+        self.fill_with(|_, _| {})
+    }
+
+    /// Same as `fill`, calling `on_grain` with the cave and the number of
+    /// grains so far every time a grain comes to rest.
+    ///
+    /// Every grain follows the previous one's path until the cell the previous
+    /// one stopped in, so instead of dropping each grain from the top, the path
+    /// is kept as a stack and the next grain carries on from the cell before.
+    fn fill_with(&mut self, mut on_grain: impl FnMut(&Cave, usize)) -> usize {
+        let mut path = vec![SOURCE];
+        let mut grains = 0;
+
+        while let Some(&(x, y)) = path.last() {
+            if y == self.bottom && self.mode == FloorMode::Abyss {
+                // This grain and every one after it falls forever
+                break;
+            }
+
+            // Down, then down-left, then down-right
+            let next = [(x, y + 1), (x - 1, y + 1), (x + 1, y + 1)]
+                .into_iter()
+                .find(|pos| self.get(*pos) == Cell::Air);
+
+            match next {
+                Some(pos) => path.push(pos),
+                None => {
+                    self.set((x, y), Cell::Sand);
+                    path.pop();
+                    grains += 1;
+                    on_grain(self, grains);
+                }
+            }
         }
 
-        // Try moving the rock to the position just below
-        let below = (pos.0, pos.1 + 1);
+        grains
+    }
 
-        // Try moving the rock to the position to the down-left
-        let down_left = (pos.0 - 1, pos.1 + 1);
+    /// Counts the grains that come to rest with a floor, without pouring any.
+    ///
+    /// With a floor, sand ends up filling every cell it can reach: the
+    /// triangle below the source, minus the cells shadowed by rocks. Going
+    /// down row by row, a cell is reached if it isn't rock and one of the
+    /// three cells above it was reached.
+    fn count_reachable(&self) -> usize {
+        assert!(
+            matches!(self.mode, FloorMode::Floor(_)),
+            "Sand only fills the whole triangle with a floor"
+        );
 
-        // Try moving the rock to the position to the down-right
-        let down_right = (pos.0 + 1, pos.1 + 1);
+        let mut row = vec![false; self.width as usize];
+        row[(SOURCE.0 - self.x_min) as usize] = true;
+        let mut count = 1;
+
+        for y in SOURCE.1 + 1..self.bottom {
+            let above = row;
+            row = (0..self.width as usize)
+                .map(|i| {
+                    let x = self.x_min + i as i32;
+                    self.get((x, y)) != Cell::Rock
+                        && above[i.saturating_sub(1)..=min(i + 1, above.len() - 1)]
+                            .iter()
+                            .any(|r| *r)
+                })
+                .collect();
+            count += row.iter().filter(|r| **r).count();
+        }
 
-        let positions_to_try = vec![below, down_left, down_right];
+        count
+    }
 
-        for pos_to_try in positions_to_try.iter() {
-            if !check_for_collision(*pos_to_try, rocks, stopped_sand) {
-                pos = *pos_to_try;
-                break;
+    /// The smallest rectangle holding the source, the rock structures and the
+    /// sand, plus the floor if there is one.
+    fn bounds(&self) -> Bounds {
+        let (mut x_min, mut y_min, mut x_max, mut y_max) = (SOURCE.0, SOURCE.1, SOURCE.0, SOURCE.1);
+
+        for y in 0..self.bottom {
+            for x in self.x_min..self.x_min + self.width {
+                if self.get((x, y)) != Cell::Air {
+                    x_min = min(x_min, x);
+                    y_min = min(y_min, y);
+                    x_max = max(x_max, x);
+                    y_max = max(y_max, y);
+                }
             }
         }
+        if let FloorMode::Floor(_) = self.mode {
+            y_max = self.bottom;
+        }
+
+        (x_min, y_min, x_max, y_max)
+    }
+
+    /// The symbol the puzzle uses for each cell in `bounds`, row by row.
+    fn symbols(&self, (x_min, y_min, x_max, y_max): Bounds) -> Vec<Vec<char>> {
+        (y_min..=y_max)
+            .map(|y| {
+                (x_min..=x_max)
+                    .map(|x| match self.get((x, y)) {
+                        Cell::Rock => '#',
+                        Cell::Sand => 'o',
+                        Cell::Air if (x, y) == SOURCE => '+',
+                        Cell::Air => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
 
-        if positions_to_try
+    fn render_text(&self, bounds: Bounds) -> String {
+        self.symbols(bounds)
             .iter()
-            .all(|p| check_for_collision(*p, rocks, stopped_sand))
-        {
-            return Some(pos);
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
+
+    // Each cell is a `scale` x `scale` square
+    fn render_ppm(&self, bounds: Bounds, scale: usize) -> String {
+        let rows = self.symbols(bounds);
+        let width = rows.first().map(|r| r.len()).unwrap_or(0);
+
+        let mut out = format!("P3\n{} {}\n255\n", width * scale, rows.len() * scale);
+        for row in rows {
+            let line: Vec<&str> = row
+                .iter()
+                .map(|c| match c {
+                    '#' => "96 96 96",
+                    'o' => "230 190 80",
+                    '+' => "220 40 40",
+                    _ => "20 20 30",
+                })
+                .flat_map(|colour| std::iter::repeat_n(colour, scale))
+                .collect();
+            for _ in 0..scale {
+                out.push_str(&line.join(" "));
+                out.push('\n');
+            }
         }
+
+        out
     }
 }
 
-fn main() {
-    let mut state = parse(read_to_string("input.txt").expect("Unable to read file"));
-
-    println!("Found {} rock structures", state.len());
-    for rock in &state {
-        println!("Rock structure with {:?} coordinates", rock.coords);
-    }
-
-    let initial_position = (500, 0);
-    let mut stopped_sand: HashSet<(i32, i32)> = HashSet::new();
-
-    // Find the lowest horizontal segment that the sand can fall to
-    // If there is no such segment, return None
-    let lowest_horizontal_segment_height = state
-        .iter()
-        .flat_map(|r| r.coords.iter())
-        .map(|c| c.1)
-        .max()
-        .unwrap()
-        + 2;
-
-    // Part 1 - Count the number of sand grains that reach the bottom before going to infinity
-    // let mut sand_count = 0;
-    // loop {
-    //     let sand_pos = get_next_collision_point_for_grain(initial_position, &state, &stopped_sand);
-
-    //     match sand_pos {
-    //         Some(pos) => {
-    //             println!("Sand stopped at {:?}", pos);
-    //             stopped_sand.insert(pos);
-    //         }
-    //         None => {
-    //             println!("Sand reached the bottom");
-    //             break;
-    //         }
-    //     }
-
-    //     sand_count += 1;
-    // }
-
-    // Part 2 - Count the number of sand grains before one stops at (500, 0)
-    // Basically brute force and takes a while, but hey! It works! :)
-    let mut sand_count = 0;
-
-    // minimum value for i32
-    let neg_inf = std::i32::MIN;
-    let max_inf = std::i32::MAX;
-
-    let floor = RockStructure {
-        coords: vec![
-            (neg_inf, lowest_horizontal_segment_height),
-            (max_inf, lowest_horizontal_segment_height),
-        ],
+/// Pours the sand, writing the cave to `dir` as a numbered PPM frame every
+/// `every` grains and once more at the end. Every frame covers the area of
+/// the final one so they line up.
+fn write_frames(cave: &Cave, dir: &str, every: usize, scale: usize) -> usize {
+    let mut filled = cave.clone();
+    filled.fill();
+    let bounds = filled.bounds();
+
+    let mut frame = 0;
+    let mut write = |cave: &Cave| {
+        let path = format!("{}/frame_{:05}.ppm", dir, frame);
+        std::fs::write(path, cave.render_ppm(bounds, scale)).unwrap();
+        frame += 1;
     };
 
-    state.push(floor);
+    let mut cave = cave.clone();
+    write(&cave);
+    let grains = cave.fill_with(|cave, grains| {
+        if grains.is_multiple_of(every) {
+            write(cave);
+        }
+    });
+    if !grains.is_multiple_of(every) {
+        write(&cave);
+    }
 
-    loop {
-        let sand_pos = get_next_collision_point_for_grain(
-            initial_position,
-            &state,
-            &stopped_sand,
-            lowest_horizontal_segment_height,
-        );
+    grains
+}
 
-        match sand_pos {
-            Some(pos) => {
-                println!("Sand stopped at {:?}", pos);
-                if pos == (500, 0) {
-                    break;
+fn parse_mode(mode: &str) -> FloorMode {
+    match mode {
+        "abyss" => FloorMode::Abyss,
+        "floor" => FloorMode::Floor(2),
+        other => panic!("Unknown floor mode '{}', expected abyss or floor", other),
+    }
+}
+
+fn main() {
+    // Usage:
+    //   day14 [input]
+    //   day14 render <input> <abyss|floor> [out.ppm] [scale]
+    //   day14 frames <input> <abyss|floor> <out dir> [grains per frame] [scale]
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(|a| a.as_str()) {
+        Some("render") => {
+            let rocks = parse(read_to_string(&args[1]).expect("Unable to read file"));
+            let mut cave = Cave::new(&rocks, parse_mode(&args[2]));
+            cave.fill();
+
+            let bounds = cave.bounds();
+            match args.get(3) {
+                Some(out) => {
+                    let scale = args.get(4).map_or(4, |a| a.parse().unwrap());
+                    std::fs::write(out, cave.render_ppm(bounds, scale)).unwrap();
                 }
-                stopped_sand.insert(pos);
-            }
-            None => {
-                panic!("Shouldn't happen!")
+                None => print!("{}", cave.render_text(bounds)),
             }
+            return;
+        }
+        Some("frames") => {
+            let rocks = parse(read_to_string(&args[1]).expect("Unable to read file"));
+            let cave = Cave::new(&rocks, parse_mode(&args[2]));
+            let every = args.get(4).map_or(100, |a| a.parse().unwrap());
+            let scale = args.get(5).map_or(4, |a| a.parse().unwrap());
+
+            std::fs::create_dir_all(&args[3]).unwrap();
+            let grains = write_frames(&cave, &args[3], every, scale);
+            println!("Wrote frames for {} grains to {}", grains, args[3]);
+            return;
         }
+        _ => {}
+    }
+
+    let file = args.first().map(|a| a.as_str()).unwrap_or("input.txt");
+    let rocks = parse(read_to_string(file).expect("Unable to read file"));
 
-        sand_count += 1;
+    // Part 1 - Count the number of sand grains that come to rest before the rest fall into the abyss
+    let mut cave = Cave::new(&rocks, FloorMode::Abyss);
+    println!("Part 1: {}", cave.fill());
+
+    // Part 2 - Count the number of sand grains until one stops at the source
+    let mut cave = Cave::new(&rocks, FloorMode::Floor(2));
+    println!("Part 2: {}", cave.count_reachable());
+    println!("Part 2 (simulated): {}", cave.fill());
+}
+
+#[test]
+fn test_example() {
+    let rocks = parse(read_to_string("ex.txt").unwrap());
+    assert_eq!(Cave::new(&rocks, FloorMode::Abyss).fill(), 24);
+    assert_eq!(Cave::new(&rocks, FloorMode::Floor(2)).fill(), 93);
+}
+
+#[test]
+fn test_count_reachable_matches_simulation() {
+    for file in ["ex.txt", "input.txt"] {
+        let rocks = parse(read_to_string(file).unwrap());
+        for offset in [1, 2, 5] {
+            let cave = Cave::new(&rocks, FloorMode::Floor(offset));
+            assert_eq!(cave.count_reachable(), cave.clone().fill());
+        }
     }
+}
 
-    println!("Sand count: {}", sand_count + 1);
+#[test]
+fn test_render_example() {
+    let rocks = parse(read_to_string("ex.txt").unwrap());
+
+    let cave = Cave::new(&rocks, FloorMode::Abyss);
+    assert_eq!(
+        cave.render_text(cave.bounds()),
+        "\
+......+...
+..........
+..........
+..........
+....#...##
+....#...#.
+..###...#.
+........#.
+........#.
+#########.
+"
+    );
+
+    let mut cave = Cave::new(&rocks, FloorMode::Abyss);
+    cave.fill();
+    assert_eq!(
+        cave.render_text(cave.bounds()),
+        "\
+......+...
+..........
+......o...
+.....ooo..
+....#ooo##
+...o#ooo#.
+..###ooo#.
+....oooo#.
+.o.ooooo#.
+#########.
+"
+    );
+
+    let mut cave = Cave::new(&rocks, FloorMode::Floor(2));
+    cave.fill();
+    let text = cave.render_text(cave.bounds());
+    assert_eq!(text.lines().next(), Some("..........o.........."));
+    assert_eq!(text.lines().last(), Some("#####################"));
+    assert_eq!(text.matches('o').count(), 93);
+
+    let ppm = cave.render_ppm(cave.bounds(), 2);
+    assert!(ppm.starts_with("P3\n42 24\n255\n"));
 }
@@ -1,10 +1,6 @@
+use interval_set::IntervalSet;
 use regex::Regex;
-use std::{
-    cmp::{max, min},
-    collections::{HashSet, VecDeque},
-    fs::File,
-    io::Read,
-};
+use std::{collections::HashSet, fs::File, io::Read};
 
 #[derive(Debug)]
 struct Reading {
@@ -12,51 +8,260 @@ struct Reading {
     closest_beacon_pos: (i32, i32),
 }
 
+struct CoverageMap {
+    readings: Vec<Reading>,
+    beacons: HashSet<(i32, i32)>,
+    sensors: HashSet<(i32, i32)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Viewport {
+    x_min: i32,
+    y_min: i32,
+    x_max: i32,
+    y_max: i32,
+}
+
 fn main() {
-    // let (input, bound) = (read_from_file("ex.txt"), 20);
-    let (input, bound) = (read_from_file("input.txt"), 4000000);
-    let x_range = (0, bound);
+    // Usage:
+    //   day15 [input] [bound] [row]
+    //   day15 render <input> <x_min> <y_min> <x_max> <y_max> <scale> [out.pgm]
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|a| a.as_str()) == Some("render") {
+        let map = CoverageMap::parse(&read_from_file(&args[1]));
+        let nums: Vec<i32> = args[2..7].iter().map(|a| a.parse().unwrap()).collect();
+        let viewport = Viewport {
+            x_min: nums[0],
+            y_min: nums[1],
+            x_max: nums[2],
+            y_max: nums[3],
+        };
+        if nums[4] <= 0 {
+            println!("The scale must be at least 1, got {}", nums[4]);
+            return;
+        }
 
-    let lines = input.lines();
+        match args.get(7) {
+            Some(out) => std::fs::write(out, map.render_pgm(viewport, nums[4])).unwrap(),
+            None => print!("{}", map.render_ascii(viewport, nums[4])),
+        }
+        return;
+    }
 
-    let regex: Regex =
-        Regex::new(r"Sensor at x=(-?\d+), y=(-?\d+): closest beacon is at x=(-?\d+), y=(-?\d+)")
-            .unwrap();
+    // ex.txt uses a bound of 20
+    let file = args.first().map(|a| a.as_str()).unwrap_or("input.txt");
+    let bound: i32 = args.get(1).map(|a| a.parse().unwrap()).unwrap_or(4000000);
+    let map = CoverageMap::parse(&read_from_file(file));
+
+    // Part 1 asks about the row halfway down the search area (10 or 2000000)
+    let y: i32 = args.get(2).map(|a| a.parse().unwrap()).unwrap_or(bound / 2);
+    println!("Covered cells on row {}: {}", y, map.count_covered(y));
+    println!("Part 1: {}", map.count_no_beacon(y));
+
+    let search_area = Viewport {
+        x_min: 0,
+        y_min: 0,
+        x_max: bound,
+        y_max: bound,
+    };
+    match map.uncovered_in(search_area).first() {
+        Some(&(x, y)) => println!("Part 2: {}", tuning_frequency((x, y))),
+        None => println!("Part 2: no uncovered cell in 0..={}", bound),
+    }
+}
 
-    let parsed = lines
-        .map(|line| parse_line(line, &regex))
-        .collect::<Vec<_>>();
+fn tuning_frequency((x, y): (i32, i32)) -> i64 {
+    x as i64 * 4000000 + y as i64
+}
+
+impl CoverageMap {
+    fn parse(input: &str) -> CoverageMap {
+        let regex: Regex = Regex::new(
+            r"Sensor at x=(-?\d+), y=(-?\d+): closest beacon is at x=(-?\d+), y=(-?\d+)",
+        )
+        .unwrap();
+
+        let readings = input
+            .lines()
+            .map(|line| parse_line(line, &regex))
+            .collect::<Vec<_>>();
+
+        let beacons = readings.iter().map(|r| r.closest_beacon_pos).collect();
+        let sensors = readings.iter().map(|r| r.sensor_pos).collect();
+
+        CoverageMap {
+            readings,
+            beacons,
+            sensors,
+        }
+    }
 
-    for y in 0..=bound {
-        let mut segments: HashSet<(i32, i32)> = HashSet::new();
-        for reading in &parsed {
+    fn row_coverage(&self, y: i32) -> IntervalSet {
+        let mut segments = IntervalSet::new();
+        for reading in &self.readings {
             calc_cover_for_row(reading, y, &mut segments);
         }
+        segments
+    }
 
-        let beacons: HashSet<(i32, i32)> = parsed.iter().map(|r| r.closest_beacon_pos).collect();
-        let sensors: HashSet<(i32, i32)> = parsed.iter().map(|r| r.sensor_pos).collect();
+    /// Number of cells on row `y` within range of at least one sensor.
+    fn count_covered(&self, y: i32) -> i64 {
+        self.row_coverage(y).len()
+    }
 
-        let mut segments: Vec<(i32, i32)> = segments.into_iter().collect();
-        merge_overlapping_segments(&mut segments);
+    /// Number of cells on row `y` where a beacon cannot be (part 1).
+    fn count_no_beacon(&self, y: i32) -> i64 {
+        count_positions_with_no_beacon_or_sensor(
+            &self.row_coverage(y),
+            &self.beacons,
+            &self.sensors,
+            y,
+        )
+    }
 
-        // println!("Segments: {:?}", segments);
+    /// Every cell in the viewport that no sensor covers, row by row.
+    fn uncovered_in(&self, viewport: Viewport) -> Vec<(i32, i32)> {
+        let mut cells = Vec::new();
 
-        let count = count_positions_with_no_beacon_or_sensor_and_in_range(
-            &segments, &beacons, &sensors, y, x_range,
-        );
-        // println!("Positions ({}): {:?}", y, count);
+        for y in viewport.y_min..=viewport.y_max {
+            let gaps = self
+                .row_coverage(y)
+                .gaps_within(viewport.x_min as i64, viewport.x_max as i64);
+
+            for (lo, hi) in gaps {
+                cells.extend((lo..=hi).map(|x| (x as i32, y)));
+            }
+        }
+
+        cells
+    }
+
+    /// Classifies the `scale`x`scale` block whose top-left cell is (x, y).
+    /// Coverage is sampled on the block's middle row.
+    fn classify_block(&self, x: i32, y: i32, scale: i32) -> Cell {
+        // Blocks at the edge of the i32 range can reach past it
+        let (x, y, scale) = (x as i64, y as i64, scale as i64);
+        let in_block = |&(px, py): &(i32, i32)| {
+            (x..x + scale).contains(&(px as i64)) && (y..y + scale).contains(&(py as i64))
+        };
+
+        if self.sensors.iter().any(in_block) {
+            return Cell::Sensor;
+        }
+        if self.beacons.iter().any(in_block) {
+            return Cell::Beacon;
+        }
+
+        let middle = (y + scale / 2).min(i32::MAX as i64) as i32;
+        let covered = self.row_coverage(middle).clamp(x, x + scale - 1).len();
+        Cell::Covered(covered as f64 / scale as f64)
+    }
+
+    fn blocks(&self, viewport: Viewport, scale: i32) -> Vec<Vec<Cell>> {
+        (viewport.y_min..=viewport.y_max)
+            .step_by(scale as usize)
+            .map(|y| {
+                (viewport.x_min..=viewport.x_max)
+                    .step_by(scale as usize)
+                    .map(|x| self.classify_block(x, y, scale))
+                    .collect()
+            })
+            .collect()
+    }
 
-        println!("\ry: {}", y);
+    // Partially covered blocks are drawn as `+`
+    fn render_ascii(&self, viewport: Viewport, scale: i32) -> String {
+        let mut out = String::new();
+
+        for row in self.blocks(viewport, scale) {
+            for cell in row {
+                out.push(match cell {
+                    Cell::Sensor => 'S',
+                    Cell::Beacon => 'B',
+                    Cell::Covered(f) if f >= 1.0 => '#',
+                    Cell::Covered(f) if f > 0.0 => '+',
+                    Cell::Covered(_) => '.',
+                });
+            }
+            out.push('\n');
+        }
 
-        if count == x_range.1 - x_range.0 {
-            println!("Segments: {:?}", segments);
-            println!("Found y?: {}", y);
+        out
+    }
 
-            break;
+    // Beacons and sensors are brighter than the grey coverage
+    fn render_pgm(&self, viewport: Viewport, scale: i32) -> String {
+        let blocks = self.blocks(viewport, scale);
+        let width = blocks.first().map(|r| r.len()).unwrap_or(0);
+
+        let mut out = format!("P2\n{} {}\n255\n", width, blocks.len());
+        for row in blocks {
+            let values: Vec<String> = row
+                .iter()
+                .map(|cell| match cell {
+                    Cell::Sensor => 255,
+                    Cell::Beacon => 220,
+                    Cell::Covered(f) => (f * 160.0) as u8,
+                })
+                .map(|v| v.to_string())
+                .collect();
+            out.push_str(&values.join(" "));
+            out.push('\n');
         }
+
+        out
     }
 }
 
+enum Cell {
+    Sensor,
+    Beacon,
+    /// Fraction of the block that is covered
+    Covered(f64),
+}
+
+#[test]
+fn ex_queries() {
+    let map = CoverageMap::parse(&read_from_file("ex.txt"));
+
+    assert_eq!(map.count_no_beacon(10), 26);
+    assert_eq!(map.count_covered(10), 27);
+
+    let search_area = Viewport {
+        x_min: 0,
+        y_min: 0,
+        x_max: 20,
+        y_max: 20,
+    };
+    assert_eq!(map.uncovered_in(search_area), vec![(14, 11)]);
+    assert_eq!(tuning_frequency((14, 11)), 56000011);
+}
+
+#[test]
+fn ex_render() {
+    let map = CoverageMap::parse(&read_from_file("ex.txt"));
+    let viewport = Viewport {
+        x_min: -2,
+        y_min: 9,
+        x_max: 25,
+        y_max: 11,
+    };
+
+    let ascii = map.render_ascii(viewport, 1);
+    assert_eq!(
+        ascii,
+        concat!(
+            ".#########################..\n",
+            "####B######################.\n",
+            "##S#############.###########\n",
+        )
+    );
+
+    let pgm = map.render_pgm(viewport, 2);
+    assert!(pgm.starts_with("P2\n14 2\n255\n"));
+}
+
 #[test]
 fn calc_part_2() {
     // Segments: [(-1184066, 3138880), (3138882, 4497514)]
@@ -80,7 +285,7 @@ fn ex_part1() {
         .map(|line| parse_line(line, &regex))
         .collect::<Vec<_>>();
 
-    let mut segments: HashSet<(i32, i32)> = HashSet::new();
+    let mut segments = IntervalSet::new();
     for reading in &parsed {
         calc_cover_for_row(reading, y, &mut segments);
     }
@@ -88,138 +293,76 @@ fn ex_part1() {
     let beacons: HashSet<(i32, i32)> = parsed.iter().map(|r| r.closest_beacon_pos).collect();
     let sensors: HashSet<(i32, i32)> = parsed.iter().map(|r| r.sensor_pos).collect();
 
-    let mut segments: Vec<(i32, i32)> = segments.into_iter().collect();
-    merge_overlapping_segments(&mut segments);
-
-    println!("Segments: {:?}", segments);
+    println!("Segments: {:?}", segments.intervals());
 
     let count = count_positions_with_no_beacon_or_sensor(&segments, &beacons, &sensors, y);
     println!("Positions: {:?}", count);
+    assert_eq!(count, 26);
 }
 
-fn merge_overlapping_segments(segments: &mut Vec<(i32, i32)>) {
-    let mut merged: Vec<(i32, i32)> = Vec::new();
-
-    segments.sort_by(|(a1, _a2), (b1, _b2)| a1.cmp(b1));
-    let mut q = VecDeque::from(segments.clone());
-
-    let (mut a, mut b) = segments[0];
-
-    while let Some(next) = q.pop_front() {
-        let (c, d) = next;
-
-        if c <= b && b <= d {
-            b = d;
-        } else if d <= b {
-            // Do nothing, it's contained
-        } else {
-            merged.push((a, b));
-            a = c;
-            b = d;
-        }
-
-        if q.is_empty() {
-            merged.push((a, b));
-        }
-    }
+#[test]
+fn count_rows_wider_than_i32() {
+    let map = CoverageMap::parse(
+        "Sensor at x=-1000000000, y=0: closest beacon is at x=0, y=0
+Sensor at x=1000000000, y=0: closest beacon is at x=0, y=0",
+    );
 
-    segments.clear();
-    segments.extend(merged);
+    assert_eq!(map.count_covered(0), 4_000_000_001);
+    assert_eq!(map.count_no_beacon(0), 4_000_000_001 - 3);
 }
 
 #[test]
-fn test_merge_overlapping_segments() {
-    let mut segments: Vec<(i32, i32)> = vec![(1, 3), (2, 4), (5, 6), (7, 8), (7, 10), (8, 9)];
-    merge_overlapping_segments(&mut segments);
-    assert_eq!(segments, vec![(1, 4), (5, 6), (7, 10)]);
+fn distances_wider_than_i32() {
+    let map =
+        CoverageMap::parse("Sensor at x=0, y=1000000000: closest beacon is at x=0, y=-1200000000");
+    // 1052516353 either side of the sensor, 1147483647 rows down
+    assert_eq!(map.count_covered(i32::MAX), 2 * 1052516353 + 1);
+
+    // The bottom blocks reach past i32::MAX
+    let viewport = Viewport {
+        x_min: 0,
+        y_min: i32::MAX - 4,
+        x_max: 4,
+        y_max: i32::MAX,
+    };
+    assert_eq!(map.render_ascii(viewport, 4), "##\n##\n");
 }
 
-fn count_positions_with_no_beacon_or_sensor(
-    segments: &Vec<(i32, i32)>,
-    known_beacons: &HashSet<(i32, i32)>,
-    sensors: &HashSet<(i32, i32)>,
-    y: i32,
-) -> i32 {
-    let beacons: Vec<&(i32, i32)> = known_beacons.iter().filter(|(_, y2)| *y2 == y).collect();
-    let sensors: Vec<&(i32, i32)> = sensors.iter().filter(|(_, y2)| *y2 == y).collect();
-
-    // calc total length of intervals
-    let mut total_count = 0;
-    for (x1, x2) in segments {
-        total_count += x2 - x1 + 1;
-    }
-
-    // for each beacon, remove the interval from the total length
-    for seg in segments {
-        for beacon in beacons.iter() {
-            let (x1, x2) = seg;
-            let (bx, _) = beacon;
-
-            if bx >= x1 && bx <= x2 {
-                total_count -= 1;
-            }
-        }
-        for sensor in sensors.iter() {
-            let (x1, x2) = seg;
-            let (bx, _) = sensor;
-
-            if bx >= x1 && bx <= x2 {
-                total_count -= 1;
-            }
-        }
-    }
-
-    total_count
+#[test]
+fn test_segments_merge_into_interval_set() {
+    let segments: IntervalSet = [(1, 3), (2, 4), (5, 6), (7, 8), (7, 10), (8, 9), (12, 13)]
+        .into_iter()
+        .collect();
+    assert_eq!(segments.intervals(), &[(1, 10), (12, 13)]);
 }
 
-fn count_positions_with_no_beacon_or_sensor_and_in_range(
-    segments: &Vec<(i32, i32)>,
+fn count_positions_with_no_beacon_or_sensor(
+    segments: &IntervalSet,
     known_beacons: &HashSet<(i32, i32)>,
     sensors: &HashSet<(i32, i32)>,
     y: i32,
-    x_range: (i32, i32),
-) -> i32 {
-    let beacons: Vec<&(i32, i32)> = known_beacons.iter().filter(|(_, y2)| *y2 == y).collect();
-    let sensors: Vec<&(i32, i32)> = sensors.iter().filter(|(_, y2)| *y2 == y).collect();
-
-    // calc total length of intervals
-    let mut total_count = 0;
-    for (x1, x2) in segments {
-        let x1 = max(*x1, x_range.0);
-        let x2 = min(*x2, x_range.1);
-        total_count += x2 - x1 + 1;
+) -> i64 {
+    let mut segments = segments.clone();
+
+    // Cells holding a beacon or a sensor can't be the distress beacon
+    for (x, _) in known_beacons
+        .iter()
+        .chain(sensors)
+        .filter(|(_, y2)| *y2 == y)
+    {
+        segments.remove(*x as i64);
     }
 
-    // for each beacon, remove the interval from the total length
-    // for seg in segments {
-    //     for beacon in beacons.iter() {
-    //         let (x1, x2) = seg;
-    //         let (bx, _) = beacon;
-
-    //         if bx >= x1 && bx <= x2 {
-    //             total_count -= 1;
-    //         }
-    //     }
-    //     for sensor in sensors.iter() {
-    //         let (x1, x2) = seg;
-    //         let (bx, _) = sensor;
-
-    //         if bx >= x1 && bx <= x2 {
-    //             total_count -= 1;
-    //         }
-    //     }
-    // }
-
-    total_count
+    segments.len()
 }
 
 #[test]
 fn test_calc_cover_for_row() {
-    let ys = vec![10, 15, 16];
-    let expecteds: Vec<usize> = vec![13, 3, 1];
+    let ys = [10, 15, 16];
+    let expecteds: [usize; 3] = [13, 3, 1];
 
     for (y, expected) in ys.iter().zip(expecteds.iter()) {
-        let mut marks: HashSet<(i32, i32)> = HashSet::new();
+        let mut marks = IntervalSet::new();
 
         calc_cover_for_row(
             &Reading {
@@ -230,7 +373,7 @@ fn test_calc_cover_for_row() {
             &mut marks,
         );
 
-        assert_eq!(marks.len(), *expected);
+        assert_eq!(marks.len() as usize, *expected);
     }
 }
 
@@ -250,8 +393,9 @@ fn test_first_try() {
     // println!("Count: {}", count);
 }
 
-fn calc_cover_for_row(reading: &Reading, y: i32, segments: &mut HashSet<(i32, i32)>) {
-    let (x1, y1) = reading.sensor_pos;
+fn calc_cover_for_row(reading: &Reading, y: i32, segments: &mut IntervalSet) {
+    let (x1, y1) = (reading.sensor_pos.0 as i64, reading.sensor_pos.1 as i64);
+    let y = y as i64;
 
     let dist = manhattan_distance(reading.sensor_pos, reading.closest_beacon_pos);
 
@@ -263,9 +407,7 @@ fn calc_cover_for_row(reading: &Reading, y: i32, segments: &mut HashSet<(i32, i3
     let y_from_s_dist = (y - y1).abs();
     let extra_dist = dist - y_from_s_dist;
 
-    let (n_lo, n_up) = (x1 - extra_dist, x1 + extra_dist);
-
-    segments.insert((n_lo, n_up));
+    segments.insert(x1 - extra_dist, x1 + extra_dist);
 }
 
 // fn bfs_up_to_n_steps(start: (i32, i32), n: i32, visited: &mut HashMap<(i32, i32), i32>) -> bool {
@@ -301,8 +443,8 @@ fn calc_cover_for_row(reading: &Reading, y: i32, segments: &mut HashSet<(i32, i3
 //     vec![(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
 // }
 
-fn manhattan_distance((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> i32 {
-    (x1 - x2).abs() + (y1 - y2).abs()
+fn manhattan_distance((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> i64 {
+    (x1 as i64 - x2 as i64).abs() + (y1 as i64 - y2 as i64).abs()
 }
 
 fn parse_line(line: &str, regex: &Regex) -> Reading {
@@ -1,297 +1,667 @@
 use core::panic;
-use indicatif::ProgressBar;
 use std::cmp::Ord;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fs::read_to_string;
+use std::thread::sleep;
+use std::time::Duration;
 
+/// The rocks from the puzzle, in the order they fall.
+const DEFAULT_PIECES: &str = "\
+####
+
+.#.
+###
+.#.
+
+..#
+..#
+###
+
+#
+#
+#
+#
+
+##
+##
+";
+
+/// One row of the chamber or of a piece, bit `x` for column `x`.
+type Row = u64;
+
+const MAX_WIDTH: usize = Row::BITS as usize;
+
+/// Column tops further below the highest rock than this count as equally deep
+/// in the surface profile, so columns that never get rock don't stop it from
+/// repeating.
+const MAX_DEPTH: i64 = 64;
+
+/// A rock shape as one bitmask per row, bottom row first. Bit `x` is set when
+/// the rock fills column `x` counted from its left edge.
+#[derive(Clone)]
 struct Piece {
-    shape: Vec<(i64, i64)>,
+    rows: Vec<Row>,
+    width: i64,
 }
 
 impl Piece {
-    fn new(piece_no: i64) -> Self {
-        match piece_no % 5 {
-            0 => Piece {
-                /* #### */
-                shape: vec![(0, 0), (1, 0), (2, 0), (3, 0)],
-            },
-            1 => Piece {
-                /*
-                .#.
-                ###
-                .#.
-                */
-                shape: vec![(1, 0), (0, -1), (1, -1), (2, -1), (1, -2)],
-            },
-            2 => Piece {
-                /*
-                ..#
-                ..#
-                ###
-                */
-                shape: vec![(2, 0), (2, -1), (0, -2), (1, -2), (2, -2)],
-            },
-            3 => Piece {
-                /*
-                #
-                #
-                #
-                #
-                */
-                shape: vec![(0, 0), (0, -1), (0, -2), (0, -3)],
-            },
-            4 => Piece {
-                /*
-                ##
-                ##
-                 */
-                shape: vec![(0, 0), (1, 0), (0, -1), (1, -1)],
-            },
-            _ => panic!("Has Math Broken?"),
+    /// Reads a shape drawn with `#` for rock and `.` for air, top row first.
+    fn parse(art: &str) -> Result<Piece, String> {
+        let mut rows = Vec::new();
+        for line in art.lines().rev() {
+            let mut mask: Row = 0;
+            for (x, c) in line.chars().enumerate() {
+                match c {
+                    '#' if x >= MAX_WIDTH => {
+                        return Err(format!("Pieces can be at most {} wide", MAX_WIDTH))
+                    }
+                    '#' => mask |= 1 << x,
+                    '.' | ' ' => {}
+                    _ => return Err(format!("Invalid piece character '{}'", c)),
+                }
+            }
+            if mask == 0 {
+                return Err("Pieces can't have empty rows".to_string());
+            }
+            rows.push(mask);
         }
-    }
 
-    fn get_coords(&self, left: i64, top: i64) -> Vec<(i64, i64)> {
-        self.shape
+        let width = rows
             .iter()
-            .map(|(x, y)| (left + x, top + y))
-            .collect()
+            .map(|r| (Row::BITS - r.leading_zeros()) as i64)
+            .max()
+            .ok_or("Empty piece")?;
+
+        Ok(Piece { rows, width })
+    }
+}
+
+/// Reads a set of pieces separated by blank lines.
+fn parse_pieces(text: &str) -> Result<Vec<Piece>, String> {
+    text.split("\n\n")
+        .map(|art| art.trim_matches('\n'))
+        .filter(|art| !art.is_empty())
+        .map(Piece::parse)
+        .collect()
+}
+
+/// What falls into the chamber and where it appears.
+struct Rules {
+    pieces: Vec<Piece>,
+    width: i64,
+    /// Gap between a new rock's left edge and the left wall
+    left_gap: i64,
+    /// Gap between a new rock's bottom edge and the top of the tower
+    bottom_gap: i64,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            pieces: parse_pieces(DEFAULT_PIECES).unwrap(),
+            width: 7,
+            left_gap: 2,
+            bottom_gap: 3,
+        }
     }
 }
 
+/// The chamber as one bitmask per row, bottom row first.
+///
+/// Rows that no rock can reach any more are dropped from the bottom, so
+/// `rows[0]` is row `floor` of the tower.
 struct Board {
-    pieces: HashMap<(i64, i64), Piece>,
+    rows: Vec<Row>,
+    /// Number of rows trimmed off below `rows`
+    floor: i64,
+    /// Falling piece, with the position of its bottom left corner
     falling_p: Option<((i64, i64), Piece)>,
+    rules: Rules,
     width: i64,
-    highest: i64,
-    collision_index: HashSet<(i64, i64)>,
-    // collision_index: BTreeSet<OrderedPair>,
+    /// Number of rows holding settled rock, i.e. one above the highest rock
+    height: i64,
+    // Highest filled y of each column, -1 if empty
+    column_tops: Vec<i64>,
 }
 
 impl Board {
-    fn new(width: i64) -> Board {
-        Board {
+    fn new(rules: Rules) -> Result<Board, String> {
+        let width = rules.width;
+        if !(1..=MAX_WIDTH as i64).contains(&width) {
+            return Err(format!("The chamber must be 1 to {} wide", MAX_WIDTH));
+        }
+        if rules.pieces.is_empty() {
+            return Err("There are no pieces to drop".to_string());
+        }
+        if rules.left_gap < 0 || rules.bottom_gap < 0 {
+            return Err("The spawn gaps can't be negative".to_string());
+        }
+        if rules
+            .pieces
+            .iter()
+            .any(|p| rules.left_gap + p.width > width)
+        {
+            return Err("A piece doesn't fit in the chamber where it appears".to_string());
+        }
+
+        Ok(Board {
+            rules,
             width,
-            pieces: HashMap::new(),
-            highest: -1,
+            rows: Vec::new(),
+            floor: 0,
+            height: 0,
             falling_p: None,
-            collision_index: HashSet::new(),
+            column_tops: vec![-1; width as usize],
+        })
+    }
+
+    /// Settled rock in row `y`, empty above the tower.
+    fn row(&self, y: i64) -> Row {
+        self.rows
+            .get((y - self.floor) as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn collides(&self, piece: &Piece, (x, y): (i64, i64)) -> bool {
+        // Check for the floor and walls
+        if x < 0 || x + piece.width > self.width || y < self.floor {
+            return true;
         }
+
+        // Check for other pieces
+        piece
+            .rows
+            .iter()
+            .enumerate()
+            .any(|(dy, mask)| self.row(y + dy as i64) & (mask << x) != 0)
     }
 
-    fn collides(&self, piece: &Piece, pos: (i64, i64)) -> bool {
-        for (x, y) in piece.get_coords(pos.0, pos.1) {
-            // Check for the floor, walls, and other pieces
-            if x < 0 || x >= self.width || y < 0 {
-                return true;
+    /// Drops the rows below the lowest one a falling rock could still reach.
+    ///
+    /// Rocks only move sideways and down, so going down from the open space
+    /// above the tower, the reachable cells of a row are the open cells below
+    /// reachable ones, spread sideways through open cells.
+    fn seal_floor(&mut self) {
+        let full = Row::MAX >> (Row::BITS as i64 - self.width);
+        let mut reach = full;
+        let mut lowest = self.rows.len();
+
+        for (i, row) in self.rows.iter().enumerate().rev() {
+            let open = !row & full;
+            reach &= open;
+            loop {
+                let spread = (reach | reach << 1 | reach >> 1) & open;
+                if spread == reach {
+                    break;
+                }
+                reach = spread;
             }
-            // for ((ox, oy), other_piece) in &self.pieces {
-            //     if other_piece.get_coords(*ox, *oy).contains(&(x, y)) {
-            //         return true;
-            //     }
-            // }
-            if self.collision_index.contains(&(x, y)) {
-                return true;
+            if reach == 0 {
+                break;
             }
+            lowest = i;
         }
 
-        false
+        self.rows.drain(..lowest);
+        self.floor += lowest as i64;
     }
 
-    fn run_simulation(&mut self, input_pattern: &str, max_rock_count: i64) {
-        let mut piece_no = 0;
-        let mut pat_iter = input_pattern.chars().cycle().enumerate();
-        let total_jets = input_pattern.len() as i64;
-        let bar = ProgressBar::new(max_rock_count as u64);
-
-        // After a rock appears, it alternates between being pushed
-        // by a jet of hot gas one unit (in the direction indicated
-        // by the next symbol in the jet pattern) and then falling
-        // one unit down.
-
-        let INSPECT_LEN = 500_000;
-        let TOTAL_LOOP_LEN = 10_000;
-        let bound = max_rock_count.min(INSPECT_LEN);
-
-        // let (div, rem) = (max_rock_count / loop_len, max_rock_count % loop_len);
-        let mut delta_heights: Vec<i64> = vec![];
-
-        while piece_no <= bound {
-            bar.set_position(piece_no as u64);
-            // self.print();
-            let falling_piece = self.falling_p.take();
-
-            match falling_piece {
-                Some(((mut x, mut y), p)) => {
-                    // Alternate between pushing and falling
-                    let (jet_i, nx) = match pat_iter.next().unwrap() {
-                        (i, '>') => {
-                            // dbg!("Jet pushes rock right");
-                            (i, x + 1)
-                        }
-                        (i, '<') => {
-                            // dbg!("Jet pushes rock left");
-                            (i, x - 1)
-                        }
-                        _ => panic!("Invalid input pattern"),
-                    };
-
-                    // If any movement would cause any part of the rock
-                    // to move into the walls, floor, or a stopped rock,
-                    // the movement instead does not occur.
-                    if !self.collides(&p, (nx, y)) {
-                        x = nx;
-                    } else {
-                        // dbg!("...but rock collides with something, so it doesn't move");
-                    }
-
-                    // Check if the piece is colliding with something below
-                    if !self.collides(&p, (x, y - 1)) {
-                        // Keep falling
-                        self.falling_p = Some(((x, y - 1), p));
-                        // dbg!("Rock falls 1 unit");
-                    } else {
-                        // Update the highest point
-                        let old_height = self.highest;
-                        p.get_coords(x, y).iter().for_each(|(x, y)| {
-                            self.highest = self.highest.max(*y);
-                            self.collision_index.insert((*x, *y));
-                        });
-
-                        delta_heights.push(self.highest - old_height);
-
-                        // Stop falling
-                        self.pieces.insert((x, y), p);
-
-                        // Start a new piece
-                        self.falling_p = None;
-                    }
-                }
-                None => {
-                    // Start a new piece
-                    // Each rock appears so that its left edge is two units
-                    // away from the left wall and its bottom edge is three
-                    // units above the highest rock in the room (or the floor,
-                    // if there isn't one).
+    /// Makes piece `piece_no` appear above the tower.
+    fn spawn(&mut self, piece_no: i64) {
+        // Each rock appears so that its left edge is two units
+        // away from the left wall and its bottom edge is three
+        // units above the highest rock in the room (or the floor,
+        // if there isn't one).
+        let piece = self.rules.pieces[piece_no as usize % self.rules.pieces.len()].clone();
+        let spawn = (self.rules.left_gap, self.height + self.rules.bottom_gap);
+        self.falling_p = Some((spawn, piece));
+    }
 
-                    let new_piece = Piece::new(piece_no);
+    /// Pushes the falling piece one unit sideways with a jet of gas.
+    fn push(&mut self, jet: char) {
+        let Some(((x, y), p)) = &self.falling_p else {
+            return;
+        };
+        let nx = match jet {
+            '>' => x + 1,
+            '<' => x - 1,
+            _ => panic!("Invalid input pattern"),
+        };
+
+        // If any movement would cause any part of the rock
+        // to move into the walls, floor, or a stopped rock,
+        // the movement instead does not occur.
+        if !self.collides(p, (nx, *y)) {
+            self.falling_p = Some(((nx, *y), p.clone()));
+        }
+    }
 
-                    let bottom_edge = new_piece.shape.iter().min_by_key(|(_, y)| y).unwrap().1;
-                    let left_edge = new_piece.shape.iter().min_by_key(|(x, _)| x).unwrap().0;
+    /// Moves the falling piece one unit down, or settles it if something is
+    /// in the way. Returns whether it's still falling.
+    fn fall(&mut self) -> bool {
+        let Some(((x, y), p)) = self.falling_p.take() else {
+            return false;
+        };
+
+        // Check if the piece is colliding with something below
+        if !self.collides(&p, (x, y - 1)) {
+            // Keep falling
+            self.falling_p = Some(((x, y - 1), p));
+            return true;
+        }
 
-                    self.falling_p =
-                        Some(((left_edge + 2, self.highest - bottom_edge + 4), new_piece));
+        // Stop falling
+        for (dy, mask) in p.rows.iter().enumerate() {
+            let ry = y + dy as i64;
+            let i = (ry - self.floor) as usize;
+            if i >= self.rows.len() {
+                self.rows.resize(i + 1, 0);
+            }
+            self.rows[i] |= mask << x;
 
-                    piece_no += 1;
-                    // dbg!("Rock {piece_no} begins falling");
+            for cx in 0..p.width {
+                if mask & (1 << cx) != 0 {
+                    let top = &mut self.column_tops[(x + cx) as usize];
+                    *top = (*top).max(ry);
                 }
             }
         }
+        self.height = self.floor + self.rows.len() as i64;
+        self.seal_floor();
+
+        false
+    }
 
-        let mut turtle = 0;
-        let mut hare = 0;
+    /// Drops piece `piece_no` until it comes to rest, using up jets from
+    /// `jets` starting at `*jet_i`.
+    fn drop_rock(&mut self, piece_no: i64, jets: &[char], jet_i: &mut usize) {
+        self.spawn(piece_no);
 
+        // After a rock appears, it alternates between being pushed
+        // by a jet of hot gas one unit (in the direction indicated
+        // by the next symbol in the jet pattern) and then falling
+        // one unit down.
         loop {
-            turtle += 1;
-            hare += 2;
-
-            if (0..TOTAL_LOOP_LEN).all(|i| delta_heights[turtle + i] == delta_heights[hare + i]) {
-                println!("Found cycle at {} with length {}", turtle, hare - turtle);
+            self.push(jets[*jet_i]);
+            *jet_i = (*jet_i + 1) % jets.len();
+            if !self.fall() {
                 break;
             }
         }
+    }
 
-        let mut answer = 0;
+    /// How far below the highest rock each column's top is, up to `MAX_DEPTH`.
+    fn surface_profile(&self) -> Vec<i64> {
+        self.column_tops
+            .iter()
+            .map(|top| (self.height - 1 - top).min(MAX_DEPTH))
+            .collect()
+    }
 
-        for i in 0..turtle {
-            answer += delta_heights[i];
-        }
+    /// Returns the height of the tower after `max_rock_count` rocks.
+    ///
+    /// Rocks are only simulated until the same piece, jet position and surface
+    /// profile come up twice. From then on the tower repeats itself, so the
+    /// rest of the height is worked out from that period. The board is left
+    /// holding only the rocks that were actually simulated, so its `height`
+    /// can be lower than the returned one.
+    fn run_simulation(&mut self, input_pattern: &str, max_rock_count: i64) -> i64 {
+        let jets: Vec<char> = input_pattern.trim().chars().collect();
+        let mut jet_i = 0;
+
+        // heights[i] is the tower height after i rocks
+        let mut heights: Vec<i64> = vec![0];
+        let mut seen: HashMap<(i64, usize, Vec<i64>), i64> = HashMap::new();
+        let mut piece_no = 0;
 
-        let cycle_len = hare - turtle;
-        let cycle_sum: i64 = delta_heights[turtle..hare].iter().sum();
-        let full_cycle_times: i64 = (max_rock_count - turtle as i64) / cycle_len as i64;
-        let remaining_steps: i64 = (max_rock_count - turtle as i64) % cycle_len as i64;
+        while piece_no < max_rock_count {
+            self.drop_rock(piece_no, &jets, &mut jet_i);
+            piece_no += 1;
+            heights.push(self.height);
+
+            let key = (
+                piece_no % self.rules.pieces.len() as i64,
+                jet_i,
+                self.surface_profile(),
+            );
+            if let Some(start) = seen.insert(key, piece_no) {
+                let cycle_len = piece_no - start;
+                let cycle_height = heights[piece_no as usize] - heights[start as usize];
+
+                let remaining = max_rock_count - piece_no;
+                let rest = (remaining % cycle_len) as usize;
+                let partial = heights[start as usize + rest] - heights[start as usize];
+
+                return heights[piece_no as usize] + remaining / cycle_len * cycle_height + partial;
+            }
+        }
 
-        answer += full_cycle_times * cycle_sum;
+        heights[piece_no as usize]
+    }
 
-        for i in 0..remaining_steps as usize {
-            answer += delta_heights[turtle + i];
+    // Starts from the top of the falling piece, or of the tower
+    fn render(&self, max_rows: i64) -> String {
+        let falling_top = self
+            .falling_p
+            .as_ref()
+            .map_or(-1, |((_, y), p)| y + p.rows.len() as i64 - 1);
+        let top = falling_top.max(self.height - 1);
+        let bottom = (top - max_rows + 1).max(self.floor);
+
+        let mut out = String::new();
+        for y in (bottom..=top).rev() {
+            out.push('|');
+            for x in 0..self.width {
+                let falling = self.falling_p.iter().any(|((px, py), piece)| {
+                    y >= *py
+                        && x >= *px
+                        && piece
+                            .rows
+                            .get((y - py) as usize)
+                            .is_some_and(|mask| mask & (1 << (x - px)) != 0)
+                });
+
+                out.push(if falling {
+                    '@'
+                } else if self.row(y) & (1 << x) != 0 {
+                    '#'
+                } else {
+                    '.'
+                });
+            }
+            out.push_str("|\n");
         }
 
-        println!("Answer: {}", answer);
-    }
+        // The floor, or a ragged edge if the tower goes on below
+        let (corner, edge) = if bottom == 0 { ('+', '-') } else { ('~', '~') };
+        out.push(corner);
+        out.extend((0..self.width).map(|_| edge));
+        out.push(corner);
+        out.push('\n');
 
-    fn print(&self) {
-        for y in (0..self.highest + 7).rev() {
-            print!("|");
-            'row: for x in 0..self.width {
-                // Check for the falling piece first
-                match &self.falling_p {
-                    Some(((px, py), piece)) => {
-                        for (tx, ty) in &piece.shape {
-                            if x == px + tx && y == py + ty {
-                                print!("@");
-                                continue 'row;
-                            }
-                        }
-                    }
-                    None => {}
-                }
+        out
+    }
+}
 
-                // Check for the remaining pieces
-                for ((px, py), piece) in &self.pieces {
-                    for (tx, ty) in &piece.shape {
-                        if x == px + tx && y == py + ty {
-                            print!("#");
-                            continue 'row;
-                        }
-                    }
-                }
+/// Drops `rock_count` rocks, redrawing the top `max_rows` rows of the chamber
+/// after every jet push and every fall, `delay` apart.
+fn animate(
+    board: &mut Board,
+    input_pattern: &str,
+    rock_count: i64,
+    max_rows: i64,
+    delay: Duration,
+) {
+    let jets: Vec<char> = input_pattern.trim().chars().collect();
+    let mut jet_i = 0;
+
+    let show = |board: &Board, caption: String| {
+        // Clear the screen and go back to the top left corner
+        print!("\x1b[2J\x1b[H");
+        println!("{}\n", caption);
+        print!("{}", board.render(max_rows));
+        sleep(delay);
+    };
+
+    for piece_no in 0..rock_count {
+        board.spawn(piece_no);
+        show(board, format!("Rock {} begins falling:", piece_no + 1));
 
-                print!(".");
+        loop {
+            let jet = jets[jet_i];
+            board.push(jet);
+            let direction = if jet == '<' { "left" } else { "right" };
+            show(board, format!("Jet {} pushes rock {}:", jet_i, direction));
+            jet_i = (jet_i + 1) % jets.len();
+
+            let falling = board.fall();
+            let caption = if falling {
+                "Rock falls 1 unit:".to_string()
+            } else {
+                format!(
+                    "Rock falls 1 unit, causing it to come to rest (height {}):",
+                    board.height
+                )
+            };
+            show(board, caption);
+            if !falling {
+                break;
             }
-            print!("|");
-            // println!();
         }
-        for _ in 0..self.width + 2 {
-            print!("-");
-        }
-        // println!();
     }
 }
 
+/// Height of the tower after `rock_count` of the puzzle's rocks fall into an
+/// empty 7 wide chamber.
+fn tower_height(input_pattern: &str, rock_count: i64) -> i64 {
+    Board::new(Rules::default())
+        .unwrap()
+        .run_simulation(input_pattern, rock_count)
+}
+
 fn main() {
-    let input_pattern = read_to_string("input.txt").unwrap();
+    // Usage:
+    //   day17 [input]
+    //   day17 custom <input> <pieces> <rocks> [width] [left gap] [bottom gap]
+    //       drop rocks drawn in the pieces file (blank line separated, `#` for
+    //       rock), defaulting to the puzzle's chamber and spawn gaps
+    //   day17 animate <input> <rocks> [rows] [delay ms]
+    //       show every jet push and fall of the first rocks
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|a| a.as_str()) == Some("custom") {
+        let input_pattern = read_to_string(&args[1]).unwrap();
+        let pieces = match parse_pieces(&read_to_string(&args[2]).unwrap()) {
+            Ok(pieces) => pieces,
+            Err(e) => {
+                println!("{}: {}", args[2], e);
+                return;
+            }
+        };
+        let rock_count: i64 = args[3].parse().unwrap();
+
+        let default = Rules::default();
+        let arg = |i: usize, default: i64| args.get(i).map_or(default, |a| a.parse().unwrap());
+        let rules = Rules {
+            pieces,
+            width: arg(4, default.width),
+            left_gap: arg(5, default.left_gap),
+            bottom_gap: arg(6, default.bottom_gap),
+        };
+
+        let mut board = match Board::new(rules) {
+            Ok(board) => board,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        println!(
+            "Height: {}",
+            board.run_simulation(&input_pattern, rock_count)
+        );
+        return;
+    }
 
-    let mut board = Board::new(7);
-    let max_rock_count: i64 = 1_000_000_000_000;
+    if args.first().map(|a| a.as_str()) == Some("animate") {
+        let input_pattern = read_to_string(&args[1]).unwrap();
+        let rock_count: i64 = args[2].parse().unwrap();
+        let max_rows = args.get(3).map_or(20, |a| a.parse().unwrap());
+        let delay = Duration::from_millis(args.get(4).map_or(100, |a| a.parse().unwrap()));
 
-    board.run_simulation(&input_pattern, max_rock_count);
-    println!("Highest point: {}", board.highest);
+        let mut board = Board::new(Rules::default()).unwrap();
+        animate(&mut board, &input_pattern, rock_count, max_rows, delay);
+        return;
+    }
+
+    let file = args.first().map(|a| a.as_str()).unwrap_or("input.txt");
+    let input_pattern = read_to_string(file).unwrap();
+
+    println!("Part 1: {}", tower_height(&input_pattern, 2022));
+    println!(
+        "Part 2: {}",
+        tower_height(&input_pattern, 1_000_000_000_000)
+    );
 }
 
 #[test]
 fn test_example_p1() {
     let input_pattern = ">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>";
 
-    let mut board = Board::new(7);
-    let max_rock_count = 2022;
+    assert_eq!(tower_height(input_pattern, 2022), 3068);
+}
+
+#[test]
+fn test_example_p2() {
+    let input_pattern = ">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>";
 
-    board.run_simulation(input_pattern, max_rock_count);
+    let mut board = Board::new(Rules::default()).unwrap();
+    let height = board.run_simulation(input_pattern, 1_000_000_000_000);
 
-    assert_eq!(board.highest, 3068 - 1);
+    assert_eq!(height, 1514285714288);
+    assert!(board.height < height);
 }
 
 #[test]
-fn test_example_p2() {
+fn test_render() {
+    let mut board = Board::new(Rules::default()).unwrap();
+
+    board.spawn(0);
+    assert_eq!(
+        board.render(10),
+        "|..@@@@.|\n|.......|\n|.......|\n|.......|\n+-------+\n"
+    );
+
+    while board.fall() {}
+    board.spawn(1);
+    board.push('<');
+    assert_eq!(
+        board.render(5),
+        "|..@....|\n|.@@@...|\n|..@....|\n|.......|\n|.......|\n~~~~~~~~~\n"
+    );
+    assert_eq!(board.render(10).lines().last(), Some("+-------+"));
+    assert!(board.render(10).contains("|..####.|"));
+}
+
+#[test]
+fn test_short_runs_are_simulated_exactly() {
     let input_pattern = ">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>";
 
-    let mut board = Board::new(7);
-    let max_rock_count = 1000000000000;
+    // Heights from the puzzle's pictures of the first rocks
+    for (rocks, height) in [(0, 0), (1, 1), (2, 4), (3, 6), (10, 17)] {
+        let mut board = Board::new(Rules::default()).unwrap();
+        assert_eq!(board.run_simulation(input_pattern, rocks), height);
+        assert_eq!(board.height, height);
+    }
+}
+
+#[test]
+fn test_custom_rules() {
+    let pieces = parse_pieces(DEFAULT_PIECES).unwrap();
+    assert_eq!(pieces.len(), 5);
+    assert_eq!(pieces[2].rows, vec![0b111, 0b100, 0b100]);
+    assert_eq!(pieces[3].width, 1);
+
+    // Bars always pushed right stack up against the right wall, a row each
+    let rules = Rules {
+        pieces: parse_pieces("##").unwrap(),
+        width: 4,
+        left_gap: 1,
+        bottom_gap: 0,
+    };
+    let mut board = Board::new(rules).unwrap();
+    assert_eq!(board.run_simulation(">", 10), 10);
+    assert_eq!(board.row(board.height - 1), 0b1100);
+
+    // The two left columns never get rock, but the tower still repeats
+    let rules = Rules {
+        pieces: parse_pieces("##").unwrap(),
+        width: 4,
+        left_gap: 1,
+        bottom_gap: 0,
+    };
+    let mut board = Board::new(rules).unwrap();
+    assert_eq!(board.run_simulation(">", 100_000_000), 100_000_000);
+    assert!(board.height < 100);
+
+    // Squares always pushed left stack up against the left wall
+    let rules = Rules {
+        pieces: parse_pieces("##\n##").unwrap(),
+        width: 4,
+        left_gap: 0,
+        bottom_gap: 3,
+    };
+    let mut board = Board::new(rules).unwrap();
+    assert_eq!(board.run_simulation("<", 10), 20);
+    assert_eq!(board.row(19), 0b0011);
+
+    // A Tetris-sized well
+    let rules = Rules {
+        pieces: parse_pieces("#####").unwrap(),
+        width: 10,
+        left_gap: 2,
+        bottom_gap: 3,
+    };
+    let mut board = Board::new(rules).unwrap();
+    assert_eq!(board.run_simulation(">", 10), 10);
+    assert_eq!(board.row(board.height - 1), 0b11111_00000);
+
+    // The widest chamber the rows can hold
+    let rules = Rules {
+        pieces: parse_pieces("#").unwrap(),
+        width: MAX_WIDTH as i64,
+        left_gap: 40,
+        bottom_gap: 3,
+    };
+    let mut board = Board::new(rules).unwrap();
+    assert_eq!(board.run_simulation("<", 100), 100);
+    // Four pushes left from column 40 before each rock lands
+    assert_eq!(board.row(board.height - 1), 1 << 36);
+}
+
+#[test]
+fn test_rule_errors() {
+    let rules = |width, left_gap, bottom_gap| Rules {
+        pieces: parse_pieces("##").unwrap(),
+        width,
+        left_gap,
+        bottom_gap,
+    };
+
+    assert!(Board::new(rules(4, 2, 0)).is_ok());
+    assert!(Board::new(rules(4, 3, 0)).is_err());
+    assert!(Board::new(rules(0, 0, 0)).is_err());
+    assert!(Board::new(rules(MAX_WIDTH as i64 + 1, 0, 0)).is_err());
+    assert_eq!(
+        Board::new(rules(7, -1, 3)).err(),
+        Some("The spawn gaps can't be negative".to_string())
+    );
+    assert!(Board::new(rules(7, 2, -5)).is_err());
+}
 
-    board.run_simulation(input_pattern, max_rock_count);
+#[test]
+fn test_piece_errors() {
+    assert!(Piece::parse(&"#".repeat(MAX_WIDTH)).is_ok());
+    assert_eq!(
+        Piece::parse(&"#".repeat(MAX_WIDTH + 1)).err(),
+        Some(format!("Pieces can be at most {} wide", MAX_WIDTH))
+    );
+    assert!(Piece::parse("#x#").is_err());
+    assert!(parse_pieces("##\n..\n##").is_err());
+}
 
-    assert_eq!(board.highest, 1514285714288);
+#[test]
+fn test_sealed_rows_are_trimmed() {
+    let jets: Vec<char> = ">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>".chars().collect();
+    let mut jet_i = 0;
+
+    let mut board = Board::new(Rules::default()).unwrap();
+    for piece_no in 0..5000 {
+        board.drop_rock(piece_no, &jets, &mut jet_i);
+    }
 
-    board.print();
+    assert!(board.floor > 0);
+    assert!(board.rows.len() < 100);
+    assert_eq!(board.floor + board.rows.len() as i64, board.height);
 }
@@ -1,3 +1,6 @@
+mod mesh;
+
+use mesh::Mesh;
 use std::{collections::HashSet, fs::read_to_string};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -7,30 +10,18 @@ struct P3D {
     z: i32,
 }
 
-const LOWER: i32 = -1;
-const UPPER: i32 = 22;
-
 impl P3D {
     fn new(x: i32, y: i32, z: i32) -> Self {
         Self { x, y, z }
     }
 
+    /// The six points sharing a face with `p`.
     fn adjacent(p: P3D) -> Vec<P3D> {
         let mut adj = Vec::new();
         for x in -1..2i32 {
             for y in -1..2i32 {
                 for z in -1..2i32 {
-                    if (x == 0 && y == 0 && z == 0) || (x.abs() + y.abs() + z.abs() != 1) {
-                        continue;
-                    }
-                    // Only account for points on [0..20]
-                    if p.x + x < LOWER
-                        || p.y + y < LOWER
-                        || p.z + z < LOWER
-                        || p.x + x > UPPER
-                        || p.y + y > UPPER
-                        || p.z + z > UPPER
-                    {
+                    if x.abs() + y.abs() + z.abs() != 1 {
                         continue;
                     }
 
@@ -42,21 +33,144 @@ impl P3D {
     }
 }
 
+/// The lava cubes, and a box around them with a one cell margin so the air
+/// around the droplet is connected.
+struct Droplet {
+    cubes: HashSet<P3D>,
+    lower: P3D,
+    upper: P3D,
+}
+
+impl Droplet {
+    fn new(cubes: HashSet<P3D>) -> Droplet {
+        let below = |axis: fn(&P3D) -> i32| cubes.iter().map(axis).min().unwrap_or(0) - 1;
+        let above = |axis: fn(&P3D) -> i32| cubes.iter().map(axis).max().unwrap_or(0) + 1;
+        let lower = P3D::new(below(|p| p.x), below(|p| p.y), below(|p| p.z));
+        let upper = P3D::new(above(|p| p.x), above(|p| p.y), above(|p| p.z));
+
+        Droplet {
+            cubes,
+            lower,
+            upper,
+        }
+    }
+
+    fn in_bounds(&self, p: &P3D) -> bool {
+        (self.lower.x..=self.upper.x).contains(&p.x)
+            && (self.lower.y..=self.upper.y).contains(&p.y)
+            && (self.lower.z..=self.upper.z).contains(&p.z)
+    }
+
+    /// The surface area of the lava that can be reached from outside.
+    ///
+    /// Floods the air around the droplet from a corner of the box, which is
+    /// never lava, counting each time the water runs into a lava cube's face.
+    fn exterior_surface(&self) -> i32 {
+        let mut queue = vec![self.lower];
+        let mut visited = HashSet::from([self.lower]);
+        let mut faces = 0;
+
+        while let Some(p) = queue.pop() {
+            for a in P3D::adjacent(p) {
+                if self.cubes.contains(&a) {
+                    faces += 1;
+                } else if self.in_bounds(&a) && visited.insert(a) {
+                    queue.push(a);
+                }
+            }
+        }
+
+        faces
+    }
+
+    /// Each pocket of air trapped inside the droplet.
+    fn air_pockets(&self) -> Vec<AirPocket> {
+        let mut outside = reachable_points(self.lower, self);
+        let mut pockets = Vec::new();
+
+        for p in self.all_points() {
+            if self.cubes.contains(&p) || outside.contains(&p) {
+                continue;
+            }
+
+            let cells = reachable_points(p, self);
+            outside.extend(cells.iter().cloned());
+            pockets.push(AirPocket {
+                surface: surface_area(&cells),
+                cells,
+            });
+        }
+
+        pockets
+    }
+
+    /// Every point in the box, lava or not.
+    fn all_points(&self) -> impl Iterator<Item = P3D> + '_ {
+        (self.lower.x..=self.upper.x)
+            .flat_map(move |x| (self.lower.y..=self.upper.y).map(move |y| (x, y)))
+            .flat_map(move |(x, y)| (self.lower.z..=self.upper.z).map(move |z| (x, y, z)))
+            .map(|(x, y, z)| P3D::new(x, y, z))
+    }
+}
+
 fn main() {
-    part_1(String::from("input.txt"));
-    part_2(String::from("input.txt"));
+    // Usage:
+    //   day18 [input]
+    //   day18 export <input> <out.obj|out.stl> [pockets]   write the droplet's exterior
+    //                                                      as a mesh, with the air pockets
+    //                                                      inside if asked
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|a| a.as_str()) == Some("export") {
+        let points = parse(read_to_string(&args[1]).unwrap());
+        let droplet = Droplet::new(points.into_iter().collect());
+        let mesh = Mesh::of_droplet(&droplet, args.get(3).map(|a| a.as_str()) == Some("pockets"));
+
+        let out = &args[2];
+        let contents = match out.rsplit('.').next() {
+            Some("obj") => mesh.to_obj(),
+            Some("stl") => mesh.to_stl(),
+            _ => panic!("Unknown mesh format for '{}', expected .obj or .stl", out),
+        };
+        std::fs::write(out, contents).unwrap();
+
+        let (rectangles, faces) = mesh.counts();
+        println!(
+            "Wrote {} faces merged into {} rectangles to {}",
+            faces, rectangles, out
+        );
+        return;
+    }
+
+    let file = args.first().map(|a| a.as_str()).unwrap_or("input.txt");
+    part_1(String::from(file));
+    part_2(String::from(file));
+}
+
+/// Air cut off from the outside of the droplet.
+struct AirPocket {
+    cells: HashSet<P3D>,
+    /// Faces of the pocket, all of them against lava
+    surface: i32,
+}
+
+impl AirPocket {
+    fn volume(&self) -> usize {
+        self.cells.len()
+    }
 }
 
-fn reachable_points(start: P3D, blocks: &HashSet<P3D>) -> HashSet<P3D> {
+/// The points of the droplet's box reachable from `start` without going
+/// through lava.
+fn reachable_points(start: P3D, droplet: &Droplet) -> HashSet<P3D> {
     let mut queue = Vec::new();
     let mut visited = HashSet::new();
 
     queue.push(start);
-    while !queue.is_empty() {
-        let p = queue.pop().unwrap();
+    while let Some(p) = queue.pop() {
         visited.insert(p);
         for a in P3D::adjacent(p).iter() {
-            if !blocks.contains(a) && !visited.contains(a) {
+            if droplet.in_bounds(a) && !droplet.cubes.contains(a) && !visited.contains(a) {
                 queue.push(*a);
             }
         }
@@ -71,8 +185,14 @@ fn part_1(filename: String) -> i32 {
 }
 
 fn part_1_inner(points: &HashSet<P3D>) -> i32 {
-    // Count covered sidesa
-    // let mut count = 0;
+    let total_count = surface_area(points);
+    println!("total count: {total_count}");
+
+    total_count
+}
+
+/// Number of faces of `points` that don't touch another point of the set.
+fn surface_area(points: &HashSet<P3D>) -> i32 {
     let mut total_count = 0;
     for p in points.iter() {
         let mut count = 0;
@@ -85,48 +205,29 @@ fn part_1_inner(points: &HashSet<P3D>) -> i32 {
         total_count += count;
     }
 
-    // println!("Total covered sides: {count}");
-
-    // let uncovered = p_set.len() * 6 - count;
-    // println!("Total uncovered sides: {uncovered}");
-    println!("total count: {total_count}");
-
     total_count
 }
 
 fn part_2(filename: String) -> i32 {
     let input = read_to_string(filename).unwrap();
     let p_set: HashSet<P3D> = parse(input).iter().cloned().collect();
-    let result = part_2_inner(p_set);
+    let droplet = Droplet::new(p_set);
+
+    let pockets = droplet.air_pockets();
+    println!(
+        "{} air pockets, holding {} cubes of air behind {} faces of lava",
+        pockets.len(),
+        pockets.iter().map(|p| p.volume()).sum::<usize>(),
+        pockets.iter().map(|p| p.surface).sum::<i32>()
+    );
+
+    let result = part_2_inner(&droplet);
     println!("Part 2: {result}");
     result
 }
 
-fn part_2_inner(points: HashSet<P3D>) -> i32 {
-    // get the reachable points from (0,0,0)
-    let reachable = reachable_points(P3D::new(0, 0, 0), &points);
-    let all_points = (LOWER..UPPER)
-        .flat_map(|x| (LOWER..UPPER).map(move |y| (x, y)))
-        .flat_map(|(x, y)| (LOWER..UPPER).map(move |z| (x, y, z)))
-        .map(|(x, y, z)| P3D::new(x, y, z))
-        .collect::<HashSet<P3D>>();
-
-    let unreachable: HashSet<P3D> = all_points.difference(&reachable).cloned().collect();
-    let mut possibly_air: HashSet<P3D> = unreachable.difference(&points).cloned().collect();
-    let total_side_area: i32 = part_1_inner(&points);
-
-    let mut air_count = possibly_air.len();
-    let mut air_side_area = 0;
-
-    while air_count > 0 {
-        let start_point = *possibly_air.iter().next().unwrap();
-        let reachable_air = reachable_points(start_point, &points);
-        air_side_area += part_1_inner(&reachable_air);
-
-        possibly_air = possibly_air.difference(&reachable_air).cloned().collect();
-        air_count = possibly_air.len();
-    }
-    total_side_area - air_side_area
+fn part_2_inner(droplet: &Droplet) -> i32 {
+    droplet.exterior_surface()
 }
 
 #[test]
@@ -158,3 +259,41 @@ fn ex_p2() {
 
     assert_eq!(result, 58);
 }
+
+#[test]
+fn ex_p2_anywhere() {
+    let points = parse(read_to_string("ex.txt").unwrap());
+
+    // The same droplet moved below zero and far past 20 on every axis
+    for offset in [-10, 40] {
+        let moved: HashSet<P3D> = points
+            .iter()
+            .map(|p| P3D::new(p.x + offset, p.y - offset, p.z + offset))
+            .collect();
+        let droplet = Droplet::new(moved);
+
+        assert!(droplet.in_bounds(&droplet.lower) && droplet.in_bounds(&droplet.upper));
+        assert_eq!(part_2_inner(&droplet), 58);
+    }
+}
+
+#[test]
+fn ex_air_pockets() {
+    let points: HashSet<P3D> = parse(read_to_string("ex.txt").unwrap())
+        .into_iter()
+        .collect();
+    let droplet = Droplet::new(points);
+
+    let pockets = droplet.air_pockets();
+    assert_eq!(pockets.len(), 1);
+    assert_eq!(pockets[0].volume(), 1);
+    assert!(pockets[0].cells.contains(&P3D::new(2, 2, 5)));
+    assert_eq!(pockets[0].surface, 6);
+
+    // Everything that isn't outside is either inside a pocket or lava
+    let pocket_surface: i32 = pockets.iter().map(|p| p.surface).sum();
+    assert_eq!(
+        surface_area(&droplet.cubes) - pocket_surface,
+        droplet.exterior_surface()
+    );
+}
@@ -0,0 +1,265 @@
+use crate::{reachable_points, Droplet, P3D};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// A rectangle of unit faces lying in one plane, all facing the same way.
+///
+/// It spans `u0..u1` and `v0..v1` on the two axes after `axis` (cyclically),
+/// at `plane` on `axis`, and faces towards +`axis` if `positive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Quad {
+    axis: usize,
+    positive: bool,
+    plane: i32,
+    u0: i32,
+    v0: i32,
+    u1: i32,
+    v1: i32,
+}
+
+impl Quad {
+    fn area(&self) -> i32 {
+        (self.u1 - self.u0) * (self.v1 - self.v0)
+    }
+
+    fn normal(&self) -> [i32; 3] {
+        let mut n = [0; 3];
+        n[self.axis] = if self.positive { 1 } else { -1 };
+        n
+    }
+
+    /// The corners, counter-clockwise seen from the side the quad faces.
+    fn corners(&self) -> [[i32; 3]; 4] {
+        let point = |u, v| {
+            let mut p = [0; 3];
+            p[self.axis] = self.plane;
+            p[(self.axis + 1) % 3] = u;
+            p[(self.axis + 2) % 3] = v;
+            p
+        };
+        let corners = [
+            point(self.u0, self.v0),
+            point(self.u1, self.v0),
+            point(self.u1, self.v1),
+            point(self.u0, self.v1),
+        ];
+
+        // u x v points along +axis, so this order faces +axis
+        if self.positive {
+            corners
+        } else {
+            [corners[0], corners[3], corners[2], corners[1]]
+        }
+    }
+
+    fn triangles(&self) -> [[[i32; 3]; 3]; 2] {
+        let [a, b, c, d] = self.corners();
+        [[a, b, c], [a, c, d]]
+    }
+}
+
+fn coord(p: &P3D, axis: usize) -> i32 {
+    [p.x, p.y, p.z][axis]
+}
+
+/// The faces of `cells` that border a cell for which `exposed` is true,
+/// merged into as few rectangles as a greedy sweep finds.
+fn surface(cells: &HashSet<P3D>, exposed: impl Fn(&P3D) -> bool) -> Vec<Quad> {
+    // Unit faces grouped by the plane they lie in
+    let mut planes: BTreeMap<(usize, bool, i32), HashSet<(i32, i32)>> = BTreeMap::new();
+    for p in cells {
+        for n in P3D::adjacent(*p) {
+            if cells.contains(&n) || !exposed(&n) {
+                continue;
+            }
+            let axis = (0..3).find(|a| coord(&n, *a) != coord(p, *a)).unwrap();
+            let positive = coord(&n, axis) > coord(p, axis);
+            let plane = coord(p, axis) + positive as i32;
+            let square = (coord(p, (axis + 1) % 3), coord(p, (axis + 2) % 3));
+            planes
+                .entry((axis, positive, plane))
+                .or_default()
+                .insert(square);
+        }
+    }
+
+    let mut quads = Vec::new();
+    for ((axis, positive, plane), mut squares) in planes {
+        let mut sorted: Vec<(i32, i32)> = squares.iter().cloned().collect();
+        sorted.sort_by_key(|(u, v)| (*v, *u));
+
+        for (u0, v0) in sorted {
+            if !squares.remove(&(u0, v0)) {
+                continue;
+            }
+
+            // Grow along u as far as the row goes, then along v while the
+            // whole span is there
+            let mut u1 = u0 + 1;
+            while squares.remove(&(u1, v0)) {
+                u1 += 1;
+            }
+            let mut v1 = v0 + 1;
+            while (u0..u1).all(|u| squares.contains(&(u, v1))) {
+                for u in u0..u1 {
+                    squares.remove(&(u, v1));
+                }
+                v1 += 1;
+            }
+
+            quads.push(Quad {
+                axis,
+                positive,
+                plane,
+                u0,
+                v0,
+                u1,
+                v1,
+            });
+        }
+    }
+
+    quads
+}
+
+/// Named parts of a mesh made of merged cube faces.
+pub struct Mesh {
+    parts: Vec<(String, Vec<Quad>)>,
+}
+
+impl Mesh {
+    /// The lava's exterior surface, and optionally each air pocket as a
+    /// separate part.
+    pub fn of_droplet(droplet: &Droplet, with_pockets: bool) -> Mesh {
+        let outside = reachable_points(droplet.lower, droplet);
+        let mut parts = vec![(
+            "lava".to_string(),
+            surface(&droplet.cubes, |n| outside.contains(n)),
+        )];
+
+        if with_pockets {
+            for (i, pocket) in droplet.air_pockets().iter().enumerate() {
+                parts.push((
+                    format!("air_pocket_{}", i + 1),
+                    surface(&pocket.cells, |_| true),
+                ));
+            }
+        }
+
+        Mesh { parts }
+    }
+
+    /// Number of rectangles, and of the unit faces merged into them.
+    pub fn counts(&self) -> (usize, i32) {
+        let quads = || self.parts.iter().flat_map(|(_, quads)| quads);
+        (quads().count(), quads().map(|q| q.area()).sum())
+    }
+
+    /// Wavefront OBJ, with one object per part.
+    pub fn to_obj(&self) -> String {
+        let mut vertices: HashMap<[i32; 3], usize> = HashMap::new();
+        let mut out = String::new();
+        let mut faces = String::new();
+
+        for (name, quads) in &self.parts {
+            faces.push_str(&format!("o {}\n", name));
+            for triangle in quads.iter().flat_map(|q| q.triangles()) {
+                let ids: Vec<String> = triangle
+                    .iter()
+                    .map(|v| {
+                        let next = vertices.len() + 1;
+                        let id = *vertices.entry(*v).or_insert_with(|| {
+                            out.push_str(&format!("v {} {} {}\n", v[0], v[1], v[2]));
+                            next
+                        });
+                        id.to_string()
+                    })
+                    .collect();
+                faces.push_str(&format!("f {}\n", ids.join(" ")));
+            }
+        }
+
+        out + &faces
+    }
+
+    /// ASCII STL, with every part in one solid.
+    pub fn to_stl(&self) -> String {
+        let mut out = String::from("solid droplet\n");
+
+        for quad in self.parts.iter().flat_map(|(_, quads)| quads) {
+            let [nx, ny, nz] = quad.normal();
+            for triangle in quad.triangles() {
+                out.push_str(&format!(
+                    "facet normal {} {} {}\n  outer loop\n",
+                    nx, ny, nz
+                ));
+                for [x, y, z] in triangle {
+                    out.push_str(&format!("    vertex {} {} {}\n", x, y, z));
+                }
+                out.push_str("  endloop\nendfacet\n");
+            }
+        }
+
+        out.push_str("endsolid droplet\n");
+        out
+    }
+}
+
+#[cfg(test)]
+fn droplet(points: &[(i32, i32, i32)]) -> Droplet {
+    Droplet::new(points.iter().map(|&(x, y, z)| P3D::new(x, y, z)).collect())
+}
+
+#[test]
+fn merges_coplanar_faces() {
+    let cube = Mesh::of_droplet(&droplet(&[(0, 0, 0)]), false);
+    let quads = &cube.parts[0].1;
+    assert_eq!(quads.len(), 6);
+
+    let obj = cube.to_obj();
+    assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 8);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 12);
+
+    // Ten unit faces, but the long sides merge into one rectangle each
+    let bar = Mesh::of_droplet(&droplet(&[(0, 0, 0), (1, 0, 0), (2, 0, 0)]), false);
+    let quads = &bar.parts[0].1;
+    assert_eq!(quads.len(), 6);
+    assert_eq!(quads.iter().map(|q| q.area()).sum::<i32>(), 14);
+}
+
+#[test]
+fn triangles_face_outwards() {
+    let mesh = Mesh::of_droplet(&droplet(&[(0, 0, 0), (1, 0, 0), (0, 1, 0)]), false);
+
+    for quad in &mesh.parts[0].1 {
+        let [a, b, c] = quad.triangles()[0];
+        let (e1, e2) = (
+            [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+            [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+        );
+        let cross = [
+            e1[1] * e2[2] - e1[2] * e2[1],
+            e1[2] * e2[0] - e1[0] * e2[2],
+            e1[0] * e2[1] - e1[1] * e2[0],
+        ];
+        let n = quad.normal();
+        assert!(cross[0] * n[0] + cross[1] * n[1] + cross[2] * n[2] > 0);
+    }
+}
+
+#[test]
+fn ex_surfaces() {
+    let points = crate::parse(std::fs::read_to_string("ex.txt").unwrap());
+    let droplet = Droplet::new(points.into_iter().collect());
+
+    let mesh = Mesh::of_droplet(&droplet, true);
+    let areas: Vec<i32> = mesh
+        .parts
+        .iter()
+        .map(|(_, quads)| quads.iter().map(|q| q.area()).sum())
+        .collect();
+    assert_eq!(areas, vec![58, 6]);
+
+    let stl = mesh.to_stl();
+    let triangles: usize = mesh.parts.iter().map(|(_, q)| q.len() * 2).sum();
+    assert_eq!(stl.matches("facet normal").count(), triangles);
+}
@@ -1,30 +1,94 @@
 use rayon::prelude::*;
 use regex::Regex;
-
-const TIME_LIMIT: u32 = 32;
+use std::collections::HashMap;
 
 fn main() {
-    let input = include_str!("../input.txt");
-    let blueprints = parse(input.to_string());
-    let initial_state = State::new();
+    // Usage:
+    //   day19 [input]                      both parts (24 minutes, then 32 for the first 3)
+    //   day19 <input> <minutes> [count]    geodes for the first `count` blueprints
+    //   day19 <input> <minutes> ids=<id,..>  geodes for the listed blueprints
+    //   day19 plan <input> <minutes> <id>  minute-by-minute build order for one blueprint
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|a| a.as_str()) == Some("plan") {
+        let blueprints = parse(std::fs::read_to_string(&args[1]).unwrap());
+        let time_limit: u32 = args[2].parse().unwrap();
+        let id: u32 = args[3].parse().unwrap();
+        let Some(blueprint) = blueprints.iter().find(|bp| bp.id == id) else {
+            println!("No blueprint {}", id);
+            return;
+        };
+
+        let plan = best_plan(blueprint, time_limit);
+        print!("{}", format_plan(blueprint, &plan, time_limit));
+        println!("Blueprint {}: {} geodes", id, plan.geodes);
+        return;
+    }
 
-    let mut results: Vec<u32> = vec![];
+    let file = args.first().map(|a| a.as_str()).unwrap_or("input.txt");
+    let blueprints = parse(std::fs::read_to_string(file).unwrap());
+
+    match args.get(1) {
+        Some(minutes) => {
+            let time_limit: u32 = minutes.parse().unwrap();
+            let selected: Vec<&Blueprint> = match args.get(2) {
+                Some(ids) if ids.starts_with("ids=") => {
+                    let ids: Vec<u32> = ids[4..].split(',').map(|id| id.parse().unwrap()).collect();
+                    if let Some(id) = ids
+                        .iter()
+                        .find(|id| blueprints.iter().all(|bp| bp.id != **id))
+                    {
+                        println!("No blueprint {}", id);
+                        return;
+                    }
+                    blueprints
+                        .iter()
+                        .filter(|bp| ids.contains(&bp.id))
+                        .collect()
+                }
+                Some(count) => blueprints.iter().take(count.parse().unwrap()).collect(),
+                None => blueprints.iter().collect(),
+            };
+
+            let geodes = run_blueprints(&selected, time_limit);
+            print_geodes(&geodes);
+            println!("Total quality: {}", total_quality(&geodes));
+            println!("Geode product: {}", geode_product(&geodes));
+        }
+        None => {
+            let all: Vec<&Blueprint> = blueprints.iter().collect();
 
-    for (i, blueprint) in blueprints.iter().take(3).enumerate() {
-        println!("Blueprint {}", i + 1);
-        // let geodes = max_geodes(&initial_state, &blueprint, 0, &mut HashMap::new());
-        let geodes = max_geodes(&initial_state, blueprint, 0);
+            let geodes = run_blueprints(&all, 24);
+            print_geodes(&geodes);
+            println!("Part 1 (total quality): {}", total_quality(&geodes));
 
-        println!("Geodes: {}", geodes);
-        let quality = (i + 1) as u32 * geodes;
-        println!("Quality for blueprint {}: {}", i + 1, quality);
-        results.push(quality);
+            let geodes = run_blueprints(&all[..all.len().min(3)], 32);
+            print_geodes(&geodes);
+            println!("Part 2 (geode product): {}", geode_product(&geodes));
+        }
     }
+}
 
-    println!(
-        "Total quality product: {}",
-        results[0] * results[1] * results[2]
-    );
+/// `(id, max geodes)` for each blueprint, solved in parallel.
+fn run_blueprints(blueprints: &[&Blueprint], time_limit: u32) -> Vec<(u32, u32)> {
+    blueprints
+        .par_iter()
+        .map(|blueprint| (blueprint.id, max_geodes(blueprint, time_limit)))
+        .collect()
+}
+
+fn print_geodes(geodes: &[(u32, u32)]) {
+    for (id, geodes) in geodes {
+        println!("Blueprint {}: {} geodes", id, geodes);
+    }
+}
+
+fn total_quality(geodes: &[(u32, u32)]) -> u32 {
+    geodes.iter().map(|(id, geodes)| id * geodes).sum()
+}
+
+fn geode_product(geodes: &[(u32, u32)]) -> u32 {
+    geodes.iter().map(|(_, geodes)| geodes).product()
 }
 
 #[test]
@@ -33,12 +97,10 @@ fn part_1() {
 
     let blueprints = parse(input.to_string());
 
-    let initial_state = State::new();
-
     let mut total_quality = 0;
     for (i, blueprint) in blueprints.iter().enumerate() {
         println!("Blueprint {}", i + 1);
-        let geodes = max_geodes(&initial_state, blueprint, 0);
+        let geodes = max_geodes(blueprint, 24);
 
         println!("Geodes: {}", geodes);
         let quality = (i + 1) as u32 * geodes;
@@ -56,184 +118,399 @@ fn example() {
 
     let blueprints = parse(input.to_string());
 
-    let initial_state = State::new();
-
     let mut total_quality = 0;
     for (i, blueprint) in blueprints.iter().enumerate() {
         println!("Blueprint {}", i + 1);
-        // let geodes = max_geodes(&initial_state, &blueprint, 0, &mut HashMap::new());
-        let geodes = max_geodes(&initial_state, blueprint, 0);
+        let geodes = max_geodes(blueprint, 24);
 
         println!("Geodes: {}", geodes);
         let quality = (i + 1) as u32 * geodes;
         println!("Quality for blueprint {}: {}", i + 1, quality);
         total_quality += quality;
-        // break; // TODO: Remove this line to run all blueprints
     }
 
     println!("Total quality: {}", total_quality);
     assert_eq!(total_quality, 33);
 }
 
+#[test]
+fn example_32_minutes() {
+    let input = include_str!("../ex.txt");
+
+    let blueprints = parse(input.to_string());
+
+    assert_eq!(max_geodes(&blueprints[0], 32), 56);
+    assert_eq!(max_geodes(&blueprints[1], 32), 62);
+}
+
+#[test]
+fn example_both_parts() {
+    let blueprints = parse(include_str!("../ex.txt").to_string());
+
+    let all: Vec<&Blueprint> = blueprints.iter().collect();
+
+    assert_eq!(total_quality(&run_blueprints(&all, 24)), 33);
+    assert_eq!(geode_product(&run_blueprints(&all, 32)), 56 * 62);
+}
+
+#[test]
+fn example_plan() {
+    let blueprints = parse(include_str!("../ex.txt").to_string());
+    let plan = best_plan(&blueprints[0], 24);
+    assert_eq!(plan.geodes, 9);
+
+    let text = format_plan(&blueprints[0], &plan, 24);
+    assert!(text.starts_with(
+        "== Minute 1 ==\n1 ore-collecting robot collects 1 ore; you now have 1 ore.\n\n"
+    ));
+    assert!(text.contains("Spend 2 ore to start building a clay-collecting robot."));
+    assert!(
+        text.contains("Spend 3 ore and 14 clay to start building an obsidian-collecting robot.")
+    );
+
+    // The last minute ends with the geodes the search promised
+    let last = text
+        .trim_end()
+        .lines()
+        .rev()
+        .find(|l| l.contains("geode-collecting"));
+    assert!(last.unwrap().ends_with("you now have 9 geode."));
+}
+
+#[test]
+fn parse_general_grammar() {
+    let input = "Blueprint 7:
+  Each ore robot costs 2 ore.
+  Each clay robot costs 3 ore.
+  Each crystal robot costs 1 ore and 2 clay.
+  Each geode robot costs 2 ore and 3 crystal and 1 clay.
+
+Blueprint 8: Each ore robot costs 1 ore. Each geode robot costs 9 ore.";
+
+    let blueprints = parse(input.to_string());
+    assert_eq!(blueprints.len(), 2);
+
+    let bp = &blueprints[0];
+    assert_eq!(bp.id, 7);
+    assert_eq!(bp.resources, vec!["ore", "clay", "crystal", "geode"]);
+    assert_eq!(bp.target, 3);
+    assert_eq!(bp.recipes[3].produces, 3);
+    assert_eq!(bp.recipes[3].costs, vec![2, 1, 3, 0]);
+
+    assert_eq!(blueprints[1].resources, vec!["ore", "geode"]);
+
+    // Quality goes by the blueprint's id, not its position
+    let all: Vec<&Blueprint> = blueprints.iter().collect();
+    let geodes = run_blueprints(&all, 14);
+    let (g7, g8) = (
+        max_geodes(&blueprints[0], 14),
+        max_geodes(&blueprints[1], 14),
+    );
+    assert!(g7 > 0 && g8 > 0);
+    assert_eq!(geodes, vec![(7, g7), (8, g8)]);
+    assert_eq!(total_quality(&geodes), 7 * g7 + 8 * g8);
+}
+
+#[test]
+fn extra_resource_matches_brute_force() {
+    let input = "Blueprint 1: Each ore robot costs 1 ore. Each clay robot costs 1 ore. \
+        Each crystal robot costs 1 ore and 1 clay. Each obsidian robot costs 1 ore and 1 crystal. \
+        Each geode robot costs 1 ore and 1 obsidian and 1 crystal.";
+    let blueprint = &parse(input.to_string())[0];
+
+    for time_limit in [8, 10, 11] {
+        assert_eq!(
+            max_geodes(blueprint, time_limit),
+            brute_force_max(blueprint, time_limit)
+        );
+    }
+    assert!(max_geodes(blueprint, 11) > 0);
+}
+
+/// Tries every choice every minute, keeping the set of distinct states.
+#[cfg(test)]
+fn brute_force_max(bp: &Blueprint, time_limit: u32) -> u32 {
+    let mut states = std::collections::HashSet::from([State::new(bp)]);
+
+    for _ in 0..time_limit {
+        let mut next = std::collections::HashSet::new();
+        for s in &states {
+            next.insert(produce(s, 1));
+            for recipe in &bp.recipes {
+                if minutes_until_affordable(s, recipe) == Some(0) {
+                    let built = produce(&pay(s, recipe), 1);
+                    next.insert(finish_building_robot(&built, recipe));
+                }
+            }
+        }
+        states = next;
+    }
+
+    states.iter().map(|s| s.resources[bp.target]).max().unwrap()
+}
+
+/// Resource and robot counts, both indexed by resource.
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
 struct State {
-    ore: u32,
-    clay: u32,
-    obsidian: u32,
-    geode: u32,
-
-    ore_robots: u32,
-    clay_robots: u32,
-    obsidian_robots: u32,
-    geode_robots: u32,
+    resources: Vec<u32>,
+    robots: Vec<u32>,
 }
 
 impl State {
-    fn new() -> Self {
+    /// Nothing in stock and a single robot of the first kind in the blueprint.
+    fn new(bp: &Blueprint) -> Self {
+        let mut robots = vec![0; bp.resources.len()];
+        robots[bp.recipes[0].produces] = 1;
+
         State {
-            ore: 0,
-            clay: 0,
-            obsidian: 0,
-            geode: 0,
-
-            ore_robots: 1,
-            clay_robots: 0,
-            obsidian_robots: 0,
-            geode_robots: 0,
+            resources: vec![0; bp.resources.len()],
+            robots,
         }
     }
 }
 
-fn max_geodes(state: &State, blueprint: &Blueprint, minute: u32) -> u32 {
-    if minute == TIME_LIMIT {
-        return state.geode;
-    }
-
-    // TODO: Prune the tree with an optimistic estimation of the max geodes
+/// Upper bound on the target resource reachable from `s` with `time_left` minutes
+/// to go: what we'd get if a new target robot were built every remaining minute.
+fn optimistic_geodes(s: &State, target: usize, time_left: u32) -> u32 {
+    s.resources[target] + s.robots[target] * time_left + time_left * time_left.saturating_sub(1) / 2
+}
 
-    possible_actions(&state, &blueprint)
-        .par_iter()
-        .map(|act| (try_building(state, act, &blueprint), act))
-        .map(|(ns, act)| (produce(&ns), act))
-        .map(|(ns, act)| finish_building_robot(&ns, act))
-        .map(|ns| max_geodes(&ns.clone(), &blueprint, minute + 1))
-        .max()
-        .unwrap()
+/// Max amount of the blueprint's target resource (geodes) after `time_limit` minutes.
+fn max_geodes(blueprint: &Blueprint, time_limit: u32) -> u32 {
+    best_plan(blueprint, time_limit).geodes
 }
 
-fn produce(s: &State) -> State {
-    // Pass time first
-    // Each robot produces 1 ore, 1 clay, 1 obsidian, or 1 geode per minute.
-    State {
-        ore: s.ore + s.ore_robots,
-        clay: s.clay + s.clay_robots,
-        obsidian: s.obsidian + s.obsidian_robots,
-        geode: s.geode + s.geode_robots,
-        ..*s
-    }
+/// A build order reaching the max amount of the target resource.
+#[derive(Debug)]
+struct Plan {
+    geodes: u32,
+    /// (minute the robot is started in, index into the blueprint's recipes)
+    builds: Vec<(u32, usize)>,
 }
 
-fn try_building(s: &State, action: &Action, blueprint: &Blueprint) -> State {
-    match action {
-        Action::MakeOreBot => State {
-            ore: s.ore - blueprint.ore_bot_ore_cost,
-            ..*s
-        },
-        Action::MakeClayBot => State {
-            ore: s.ore - blueprint.clay_bot_ore_cost,
-            ..*s
-        },
-        Action::MakeObsidianBot => State {
-            ore: s.ore - blueprint.obsidian_bot_ore_cost,
-            clay: s.clay - blueprint.obsidian_robot_clay_cost,
-            ..*s
-        },
-        Action::MakeGeodeBot => State {
-            ore: s.ore - blueprint.geode_bot_ore_cost,
-            obsidian: s.obsidian - blueprint.geode_robot_obsidian_cost,
-            ..*s
-        },
-        Action::Wait => s.clone(),
+fn best_plan(blueprint: &Blueprint, time_limit: u32) -> Plan {
+    // No point producing more of a resource per minute than we can spend per minute
+    let max_spend = (0..blueprint.resources.len())
+        .map(|r| match r == blueprint.target {
+            true => u32::MAX,
+            false => blueprint
+                .recipes
+                .iter()
+                .map(|rc| rc.costs[r])
+                .max()
+                .unwrap(),
+        })
+        .collect();
+
+    let mut search = Search {
+        blueprint,
+        time_limit,
+        max_spend,
+        best: 0,
+        path: Vec::new(),
+        best_path: Vec::new(),
+        seen: HashMap::new(),
+    };
+
+    search.dfs(State::new(blueprint), time_limit);
+    Plan {
+        geodes: search.best,
+        builds: search.best_path,
     }
 }
 
-fn finish_building_robot(s: &State, action: &Action) -> State {
-    match action {
-        Action::MakeOreBot => State {
-            ore_robots: s.ore_robots + 1,
-            ..*s
-        },
-        Action::MakeClayBot => State {
-            clay_robots: s.clay_robots + 1,
-            ..*s
-        },
-        Action::MakeObsidianBot => State {
-            obsidian_robots: s.obsidian_robots + 1,
-            ..*s
-        },
-        Action::MakeGeodeBot => State {
-            geode_robots: s.geode_robots + 1,
-            ..*s
-        },
-        Action::Wait => s.clone(),
-    }
+struct Search<'a> {
+    blueprint: &'a Blueprint,
+    time_limit: u32,
+    max_spend: Vec<u32>,
+    best: u32,
+    // Builds leading to the current state, and to the best result so far
+    path: Vec<(u32, usize)>,
+    best_path: Vec<(u32, usize)>,
+    // For each (robots, time left), the resources of the states already explored.
+    // A state with no more of any resource than one of these can't do better.
+    seen: HashMap<(Vec<u32>, u32), Vec<Vec<u32>>>,
 }
 
-fn possible_actions(s: &State, bp: &Blueprint) -> Vec<Action> {
-    // Given the current state of the factory, determine
-    // what actions are possible.
+impl Search<'_> {
+    fn dfs(&mut self, state: State, time_left: u32) {
+        let target = self.blueprint.target;
 
-    let mut actions: Vec<Action> = Vec::new();
+        // Geodes we'd end up with if nothing else gets built
+        let idle = state.resources[target] + state.robots[target] * time_left;
+        if idle > self.best {
+            self.best = idle;
+            self.best_path = self.path.clone();
+        }
 
-    let max_ore_cost = bp
-        .ore_bot_ore_cost
-        .max(bp.clay_bot_ore_cost)
-        .max(bp.obsidian_bot_ore_cost)
-        .max(bp.geode_bot_ore_cost);
-    let max_clay_cost = bp.clay_bot_ore_cost.max(bp.obsidian_robot_clay_cost);
+        if optimistic_geodes(&state, target, time_left) <= self.best
+            || self.is_dominated(&state, time_left)
+        {
+            return;
+        }
 
-    if s.ore >= bp.geode_bot_ore_cost && s.obsidian >= bp.geode_robot_obsidian_cost {
-        actions.push(Action::MakeGeodeBot);
-        return actions; // prioritize always making a geode robot
+        // Recipes are declared from cheapest to most valuable, so try them backwards
+        for (i, recipe) in self.blueprint.recipes.iter().enumerate().rev() {
+            if state.robots[recipe.produces] >= self.max_spend[recipe.produces] {
+                continue;
+            }
+
+            // Build as soon as we can afford it, and only if the robot gets
+            // at least one minute to work afterwards
+            if let Some(wait) = minutes_until_affordable(&state, recipe) {
+                if wait + 1 < time_left {
+                    let next = pay(&produce(&state, wait), recipe);
+                    let next = finish_building_robot(&produce(&next, 1), recipe);
+
+                    let minute = self.time_limit - time_left + wait + 1;
+                    self.path.push((minute, i));
+                    self.dfs(next, time_left - wait - 1);
+                    self.path.pop();
+                }
+            }
+        }
     }
 
-    if s.ore >= bp.obsidian_bot_ore_cost
-        && s.clay >= bp.obsidian_robot_clay_cost
-        && s.obsidian_robots < bp.geode_robot_obsidian_cost
-    {
-        actions.push(Action::MakeObsidianBot);
+    fn is_dominated(&mut self, s: &State, time_left: u32) -> bool {
+        let explored = self.seen.entry((s.robots.clone(), time_left)).or_default();
+
+        if explored
+            .iter()
+            .any(|r| r.iter().zip(&s.resources).all(|(a, b)| a >= b))
+        {
+            return true;
+        }
+
+        explored.push(s.resources.clone());
+        false
     }
-    if s.ore >= bp.ore_bot_ore_cost && s.ore_robots < max_ore_cost {
-        actions.push(Action::MakeOreBot);
+}
+
+/// Minutes of production needed before `recipe` is affordable,
+/// or None if we don't have the robots to ever afford it.
+fn minutes_until_affordable(s: &State, recipe: &Recipe) -> Option<u32> {
+    let mut wait = 0;
+
+    for (r, &cost) in recipe.costs.iter().enumerate() {
+        let have = s.resources[r];
+        if have >= cost {
+            continue;
+        }
+        if s.robots[r] == 0 {
+            return None;
+        }
+        wait = wait.max((cost - have).div_ceil(s.robots[r]));
     }
 
-    if s.ore >= bp.clay_bot_ore_cost // If we have enough ore to make a clay robot
-    && s.clay_robots < max_clay_cost
-    // and we don't have too many clay robots
-    {
-        actions.push(Action::MakeClayBot);
+    Some(wait)
+}
+
+fn produce(s: &State, minutes: u32) -> State {
+    // Each robot produces one unit of its resource per minute.
+    State {
+        resources: s
+            .resources
+            .iter()
+            .zip(&s.robots)
+            .map(|(have, robots)| have + robots * minutes)
+            .collect(),
+        robots: s.robots.clone(),
     }
+}
 
-    if s.ore < max_ore_cost
-        || (s.clay < bp.obsidian_robot_clay_cost && s.clay_robots > 0)
-        || (s.obsidian < bp.geode_robot_obsidian_cost && s.obsidian_robots > 0)
-    {
-        actions.push(Action::Wait);
+fn pay(s: &State, recipe: &Recipe) -> State {
+    State {
+        resources: s
+            .resources
+            .iter()
+            .zip(&recipe.costs)
+            .map(|(have, cost)| have - cost)
+            .collect(),
+        robots: s.robots.clone(),
     }
+}
+
+fn finish_building_robot(s: &State, recipe: &Recipe) -> State {
+    let mut next = s.clone();
+    next.robots[recipe.produces] += 1;
+    next
+}
 
-    if actions.len() == 0 {
-        actions.push(Action::Wait);
+/// Replays `plan` minute by minute, worded like the puzzle's walkthrough.
+fn format_plan(bp: &Blueprint, plan: &Plan, time_limit: u32) -> String {
+    let mut out = String::new();
+    let mut state = State::new(bp);
+
+    for minute in 1..=time_limit {
+        out.push_str(&format!("== Minute {} ==\n", minute));
+
+        let building = plan
+            .builds
+            .iter()
+            .find(|(m, _)| *m == minute)
+            .map(|(_, i)| &bp.recipes[*i]);
+
+        if let Some(recipe) = building {
+            let costs: Vec<String> = recipe
+                .costs
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c > 0)
+                .map(|(r, c)| format!("{} {}", c, bp.resources[r]))
+                .collect();
+            let robot = robot_name(bp, recipe.produces);
+            let article = match robot.starts_with(['a', 'e', 'i', 'o', 'u']) {
+                true => "an",
+                false => "a",
+            };
+            out.push_str(&format!(
+                "Spend {} to start building {} {}.\n",
+                costs.join(" and "),
+                article,
+                robot
+            ));
+            state = pay(&state, recipe);
+        }
+
+        for (r, &robots) in state.robots.iter().enumerate() {
+            if robots == 0 {
+                continue;
+            }
+            let (plural, verb) = match robots {
+                1 => ("", "collects"),
+                _ => ("s", "collect"),
+            };
+            out.push_str(&format!(
+                "{} {}{} {} {} {}; you now have {} {}.\n",
+                robots,
+                robot_name(bp, r),
+                plural,
+                verb,
+                robots,
+                bp.resources[r],
+                state.resources[r] + robots,
+                bp.resources[r]
+            ));
+        }
+        state = produce(&state, 1);
+
+        if let Some(recipe) = building {
+            state = finish_building_robot(&state, recipe);
+            out.push_str(&format!(
+                "The new {} is ready; you now have {} of them.\n",
+                robot_name(bp, recipe.produces),
+                state.robots[recipe.produces]
+            ));
+        }
+
+        out.push('\n');
     }
 
-    actions
+    out
 }
 
-enum Action {
-    MakeOreBot,
-    MakeClayBot,
-    MakeObsidianBot,
-    MakeGeodeBot,
-    Wait,
+fn robot_name(bp: &Blueprint, resource: usize) -> String {
+    format!("{}-collecting robot", bp.resources[resource])
 }
 
 fn parse(filename: String) -> Vec<Blueprint> {
@@ -242,29 +519,80 @@ fn parse(filename: String) -> Vec<Blueprint> {
     // Format examples:
     // Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.
     // Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsidian robot costs 3 ore and 8 clay.  Each geode robot costs 3 ore and 12 obsidian.
+    //
+    // Any resource names and any number of robots (in any number of lines) are
+    // accepted. We start with one robot of the first kind listed, and the last
+    // kind listed is the resource to maximize.
 
-    let r: Regex = Regex::new(r"Blueprint \d+: Each ore robot costs (\d+) ore. Each clay robot costs (\d+) ore. Each obsidian robot costs (\d+) ore and (\d+) clay. Each geode robot costs (\d+) ore and (\d+) obsidian.").unwrap();
+    let blueprint_r: Regex = Regex::new(r"Blueprint (\d+):").unwrap();
+    let robot_r: Regex = Regex::new(r"Each (\w+) robot costs ([^.]+)\.").unwrap();
+    let cost_r: Regex = Regex::new(r"(\d+) (\w+)").unwrap();
 
+    let starts: Vec<_> = blueprint_r.captures_iter(&filename).collect();
     let mut blueprints: Vec<Blueprint> = Vec::new();
 
-    for line in filename.lines() {
-        let caps = r.captures(line).unwrap();
+    for (i, caps) in starts.iter().enumerate() {
+        let whole = caps.get(0).unwrap();
+        let end = starts
+            .get(i + 1)
+            .map(|next| next.get(0).unwrap().start())
+            .unwrap_or(filename.len());
+        let text = &filename[whole.end()..end];
+
+        let mut resources: Vec<String> = Vec::new();
+        let mut index_of = |name: &str| match resources.iter().position(|r| r == name) {
+            Some(i) => i,
+            None => {
+                resources.push(name.to_string());
+                resources.len() - 1
+            }
+        };
+
+        // Costs are collected as (resource, amount) first, since later robots
+        // can introduce new resources
+        let mut raw_recipes = Vec::new();
+        for robot in robot_r.captures_iter(text) {
+            let produces = index_of(&robot[1]);
+            let costs: Vec<(usize, u32)> = cost_r
+                .captures_iter(&robot[2])
+                .map(|c| (index_of(&c[2]), c[1].parse::<u32>().unwrap()))
+                .collect();
+            raw_recipes.push((produces, costs));
+        }
+
+        let recipes: Vec<Recipe> = raw_recipes
+            .into_iter()
+            .map(|(produces, costs)| {
+                let mut vector = vec![0; resources.len()];
+                for (r, amount) in costs {
+                    vector[r] += amount;
+                }
+                Recipe {
+                    produces,
+                    costs: vector,
+                }
+            })
+            .collect();
+
         blueprints.push(Blueprint {
-            ore_bot_ore_cost: caps.get(1).unwrap().as_str().parse::<u32>().unwrap(),
-            clay_bot_ore_cost: caps.get(2).unwrap().as_str().parse::<u32>().unwrap(),
-            obsidian_bot_ore_cost: caps.get(3).unwrap().as_str().parse::<u32>().unwrap(),
-            obsidian_robot_clay_cost: caps.get(4).unwrap().as_str().parse::<u32>().unwrap(),
-            geode_bot_ore_cost: caps.get(5).unwrap().as_str().parse::<u32>().unwrap(),
-            geode_robot_obsidian_cost: caps.get(6).unwrap().as_str().parse::<u32>().unwrap(),
+            id: caps[1].parse::<u32>().unwrap(),
+            target: recipes.last().unwrap().produces,
+            resources,
+            recipes,
         });
     }
     blueprints
 }
+
 struct Blueprint {
-    ore_bot_ore_cost: u32,
-    clay_bot_ore_cost: u32,
-    obsidian_bot_ore_cost: u32,
-    obsidian_robot_clay_cost: u32,
-    geode_bot_ore_cost: u32,
-    geode_robot_obsidian_cost: u32,
+    id: u32,
+    resources: Vec<String>,
+    recipes: Vec<Recipe>,
+    target: usize,
+}
+
+/// A robot that produces one `produces` per minute, for `costs` (indexed by resource).
+struct Recipe {
+    produces: usize,
+    costs: Vec<u32>,
 }
@@ -0,0 +1,222 @@
+use crate::rational::Rational;
+use crate::{lookup, EvalError, Exp};
+use std::collections::{HashMap, HashSet};
+
+/// A subtree either folded down to a constant, or printed as a formula.
+#[derive(Clone)]
+enum Infix {
+    Const(Rational),
+    Expr(String),
+}
+
+impl Infix {
+    fn text(&self) -> String {
+        match self {
+            Infix::Const(c) if c.to_integer().is_some() => c.to_string(),
+            // Keep fractions together when they're an operand
+            Infix::Const(c) => format!("({})", c),
+            Infix::Expr(e) => e.clone(),
+        }
+    }
+}
+
+/// Builds each monkey's `Infix` once, so shared monkeys are only folded once.
+struct Printer<'a, 'b> {
+    opers: &'b HashMap<&'a str, Exp<'a>>,
+    unknown: &'b str,
+    values: HashMap<&'a str, Infix>,
+    path: Vec<&'a str>,
+}
+
+impl<'a> Printer<'a, '_> {
+    fn infix(&mut self, name: &str) -> Result<Infix, EvalError> {
+        if name == self.unknown {
+            return Ok(Infix::Expr(name.to_string()));
+        }
+        if let Some(v) = self.values.get(name) {
+            return Ok(v.clone());
+        }
+
+        let (name, exp) = lookup(self.opers, name, &self.path)?;
+        let (l, r, op) = match *exp {
+            Exp::Num(n) => return Ok(Infix::Const(Rational::from(n))),
+            Exp::Plus(l, r) => (l, r, '+'),
+            Exp::Minus(l, r) => (l, r, '-'),
+            Exp::Mul(l, r) => (l, r, '*'),
+            Exp::Div(l, r) => (l, r, '/'),
+        };
+
+        self.path.push(name);
+        let (l, r) = (self.infix(l)?, self.infix(r)?);
+        self.path.pop();
+
+        let value = match (l, r) {
+            (Infix::Const(l), Infix::Const(r)) if !(op == '/' && r.is_zero()) => {
+                let folded = match op {
+                    '+' => l.checked_add(r),
+                    '-' => l.checked_sub(r),
+                    '*' => l.checked_mul(r),
+                    _ => l.checked_div(r),
+                };
+                Infix::Const(folded.ok_or_else(|| EvalError::Overflow(name.to_string()))?)
+            }
+            (l, r) => Infix::Expr(format!("({} {} {})", l.text(), op, r.text())),
+        };
+
+        self.values.insert(name, value.clone());
+        Ok(value)
+    }
+}
+
+/// Monkey `name`'s full expression as a parenthesized infix formula, keeping
+/// `unknown` as a variable and folding every subtree that doesn't use it.
+pub fn formula(opers: &HashMap<&str, Exp>, name: &str, unknown: &str) -> Result<String, EvalError> {
+    let mut printer = Printer {
+        opers,
+        unknown,
+        values: HashMap::new(),
+        path: Vec::new(),
+    };
+    let text = printer.infix(name)?.text();
+
+    // The outermost parentheses don't help anyone
+    Ok(match text.starts_with('(') && text.ends_with(')') {
+        true => text[1..text.len() - 1].to_string(),
+        false => text,
+    })
+}
+
+/// The monkeys reachable from `root` in depth-first order, and which of them
+/// depend on `unknown`.
+fn walk<'a>(
+    opers: &HashMap<&'a str, Exp<'a>>,
+    name: &str,
+    unknown: &str,
+    path: &mut Vec<&'a str>,
+    order: &mut Vec<&'a str>,
+    on_path: &mut HashMap<&'a str, bool>,
+) -> Result<bool, EvalError> {
+    if let Some(depends) = on_path.get(name) {
+        return Ok(*depends);
+    }
+
+    let (name, exp) = lookup(opers, name, path)?;
+    order.push(name);
+    path.push(name);
+
+    let mut depends = name == unknown;
+    if let Exp::Plus(l, r) | Exp::Minus(l, r) | Exp::Mul(l, r) | Exp::Div(l, r) = *exp {
+        // Both sides are walked, so every monkey shows up in the graph
+        let left = walk(opers, l, unknown, path, order, on_path)?;
+        let right = walk(opers, r, unknown, path, order, on_path)?;
+        depends |= left || right;
+    }
+
+    path.pop();
+    on_path.insert(name, depends);
+    Ok(depends)
+}
+
+/// The dependency graph of `root` in Graphviz DOT format, with the monkeys and
+/// edges leading from `root` to `unknown` highlighted.
+pub fn to_dot(opers: &HashMap<&str, Exp>, root: &str, unknown: &str) -> Result<String, EvalError> {
+    let mut order = Vec::new();
+    let mut on_path = HashMap::new();
+    walk(
+        opers,
+        root,
+        unknown,
+        &mut Vec::new(),
+        &mut order,
+        &mut on_path,
+    )?;
+
+    let highlight = |name: &str| on_path[name];
+    let mut out = String::from("digraph monkeys {\n    node [shape=box];\n");
+
+    for name in &order {
+        let label = match opers[name] {
+            Exp::Num(n) => n.to_string(),
+            Exp::Plus(l, r) => format!("{} + {}", l, r),
+            Exp::Minus(l, r) => format!("{} - {}", l, r),
+            Exp::Mul(l, r) => format!("{} * {}", l, r),
+            Exp::Div(l, r) => format!("{} / {}", l, r),
+        };
+        let style = match highlight(name) {
+            true => ", color=red, penwidth=2",
+            false => "",
+        };
+        out.push_str(&format!(
+            "    {} [label=\"{}\\n{}\"{}];\n",
+            name, name, label, style
+        ));
+    }
+
+    let mut seen_edges = HashSet::new();
+    for name in &order {
+        if let Exp::Plus(l, r) | Exp::Minus(l, r) | Exp::Mul(l, r) | Exp::Div(l, r) = opers[name] {
+            for child in [l, r] {
+                if !seen_edges.insert((*name, child)) {
+                    continue;
+                }
+                let style = match highlight(child) {
+                    true => " [color=red, penwidth=2]",
+                    false => "",
+                };
+                out.push_str(&format!("    {} -> {}{};\n", name, child, style));
+            }
+        }
+    }
+
+    out.push_str("}\n");
+    Ok(out)
+}
+
+#[cfg(test)]
+use crate::parse;
+
+#[test]
+fn example_formula() {
+    let opers = parse(include_str!("../ex.txt"));
+
+    assert_eq!(
+        formula(&opers, "pppw", "humn").unwrap(),
+        "(4 + (2 * (humn - 3))) / 4"
+    );
+    assert_eq!(formula(&opers, "sjmn", "humn").unwrap(), "150");
+    assert_eq!(formula(&opers, "root", "nobody").unwrap(), "152");
+
+    let opers = parse("root: a / b\na: humn + c\nb: 4\nc: d / b\nd: 6");
+    assert_eq!(
+        formula(&opers, "root", "humn").unwrap(),
+        "(humn + (3/2)) / 4"
+    );
+}
+
+#[test]
+fn formula_shared_monkeys_once() {
+    let mut input = String::from("m60: 1\n");
+    for i in 0..60 {
+        input.push_str(&format!("m{}: m{} + m{}\n", i, i + 1, i + 1));
+    }
+
+    assert_eq!(
+        formula(&parse(&input), "m0", "humn").unwrap(),
+        (1u64 << 60).to_string()
+    );
+}
+
+#[test]
+fn example_dot() {
+    let opers = parse(include_str!("../ex.txt"));
+    let dot = to_dot(&opers, "root", "humn").unwrap();
+
+    assert!(dot.starts_with("digraph monkeys {\n"));
+    assert!(dot.contains("    root [label=\"root\\npppw + sjmn\", color=red, penwidth=2];\n"));
+    assert!(dot.contains("    sjmn [label=\"sjmn\\ndrzm * dbpl\"];\n"));
+    assert!(dot.contains("    ptdq -> humn [color=red, penwidth=2];\n"));
+    assert!(dot.contains("    root -> sjmn;\n"));
+
+    // Every monkey appears once
+    assert_eq!(dot.matches("[label=").count(), 15);
+}
@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+mod export;
+mod rational;
+
+use rational::Rational;
+use std::{collections::HashMap, fmt};
 
 #[derive(Debug, Clone)]
 enum Exp<'a> {
@@ -10,211 +14,605 @@ enum Exp<'a> {
 }
 
 fn main() {
-    let input = parse(include_str!("../input.txt"));
+    // Usage:
+    //   day21 [input] [unknown] [root] [relation]
+    //   day21 formula <input> <monkey> [unknown]   infix formula, constants folded
+    //   day21 dot <input> [root] [unknown]         Graphviz DOT of the monkey graph
+    // Defaults to the puzzle: input.txt, solving for humn with root's operands equal.
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    let left;
-    let right;
+    if let Some(mode @ ("formula" | "dot")) = args.first().map(|a| a.as_str()) {
+        let arg =
+            |i: usize, default: &'static str| args.get(i).map(|a| a.as_str()).unwrap_or(default);
+        let contents = std::fs::read_to_string(&args[1]).unwrap();
+        let input = parse(&contents);
 
-    match input.get("root").unwrap() {
-        Exp::Num(_) => panic!("Root is a number"),
-        Exp::Plus(l, r) => {
-            left = l;
-            right = r;
-        }
-        Exp::Minus(l, r) => {
-            left = l;
-            right = r;
-        }
-        Exp::Mul(l, r) => {
-            left = l;
-            right = r;
-        }
-        Exp::Div(l, r) => {
-            left = l;
-            right = r;
+        let out = match mode {
+            "formula" => export::formula(&input, &args[2], arg(3, "humn")),
+            _ => export::to_dot(&input, arg(2, "root"), arg(3, "humn")),
+        };
+        match out {
+            Ok(out) => println!("{}", out),
+            Err(e) => println!("{}", e),
         }
+        return;
     }
 
-    let (ans_left, ans_right) = (eval(&input, left), eval(&input, right));
-    println!("Left: {}, Right: {}", ans_left, ans_right);
-
-    let mut invs_map = create_inverse_map_from_node_humn(&input, "humn", "root");
+    let arg = |i: usize, default: &'static str| args.get(i).map(|a| a.as_str()).unwrap_or(default);
 
-    let target_val = if has_node(&input, "root", "humn") {
-        println!("Target is in left subtree of root");
-        ans_right
-    } else {
-        println!("Target is in right subtree of root");
-        ans_left
+    let contents = std::fs::read_to_string(arg(0, "input.txt")).unwrap();
+    let input = parse(&contents);
+    let (unknown, root) = (arg(1, "humn"), arg(2, "root"));
+    let relation = match arg(3, "=") {
+        "=" => Relation::Eq,
+        "<" => Relation::Lt,
+        ">" => Relation::Gt,
+        other => panic!("Unknown relation '{}', expected =, < or >", other),
     };
 
-    invs_map.insert(
-        if target_val == ans_left { right } else { left },
-        Exp::Num(target_val),
-    );
+    match eval(&input, root) {
+        Ok(value) => println!("Part 1: {}", value),
+        Err(e) => println!("Part 1: {}", e),
+    }
 
-    let new_input: HashMap<&str, Exp> = input
-        .iter()
-        .filter(|(k, _)| !invs_map.contains_key(*k))
-        .map(|(k, v)| (*k, v.clone()))
-        .collect();
+    match solve(&input, unknown, root, relation) {
+        Ok(solution) => println!("Part 2: {} {}", unknown, solution),
+        Err(e) => println!("Part 2: {}", e),
+    }
+}
 
-    let ans = eval_with_inverse(&new_input, &invs_map, "humn");
-    println!("Answer: {}", ans);
+#[derive(Debug, PartialEq)]
+enum EvalError {
+    UnknownMonkey(String),
+    /// The monkeys in the loop, starting and ending with the same one
+    Cycle(Vec<String>),
+    Overflow(String),
+    DivisionByZero(String),
 }
 
-fn has_node<'a>(opers: &'a HashMap<&str, Exp>, src_node: &'a str, target_node: &'a str) -> bool {
-    if src_node == target_node {
-        return true;
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::UnknownMonkey(name) => write!(f, "no monkey named '{}'", name),
+            EvalError::Cycle(names) => {
+                write!(f, "monkeys wait on each other: {}", names.join(" -> "))
+            }
+            EvalError::Overflow(name) => write!(f, "'{}' overflows a 64-bit integer", name),
+            EvalError::DivisionByZero(name) => write!(f, "'{}' divides by zero", name),
+        }
     }
+}
 
-    match opers[src_node] {
-        Exp::Num(_) => return false,
-        Exp::Plus(l, r) => {
-            return has_node(opers, l, target_node) || has_node(opers, r, target_node)
-        }
-        Exp::Minus(l, r) => {
-            return has_node(opers, l, target_node) || has_node(opers, r, target_node)
+/// Finds monkey `name`, which is about to be entered from the monkeys in `path`.
+fn lookup<'a, 'b>(
+    opers: &'b HashMap<&'a str, Exp<'a>>,
+    name: &str,
+    path: &[&str],
+) -> Result<(&'a str, &'b Exp<'a>), EvalError> {
+    if let Some(start) = path.iter().position(|p| *p == name) {
+        let mut cycle: Vec<String> = path[start..].iter().map(|p| p.to_string()).collect();
+        cycle.push(name.to_string());
+        return Err(EvalError::Cycle(cycle));
+    }
+
+    opers
+        .get_key_value(name)
+        .map(|(k, v)| (*k, v))
+        .ok_or_else(|| EvalError::UnknownMonkey(name.to_string()))
+}
+
+/// `a * x + b`, where x is the unknown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Linear {
+    a: Rational,
+    b: Rational,
+}
+
+#[derive(Debug, PartialEq)]
+enum SolveError {
+    /// The unknown ends up multiplied by itself or in a divisor
+    NotLinear,
+    DivisionByZero,
+    /// The coefficients of this monkey don't fit in 128 bits
+    Overflow(String),
+    Eval(EvalError),
+    RootIsNumber(String),
+    NoSolution,
+    NoIntegerSolution(Rational),
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::NotLinear => write!(f, "the equation is not linear in the unknown"),
+            SolveError::DivisionByZero => write!(f, "division by zero"),
+            SolveError::Overflow(name) => write!(f, "'{}' overflows a 128-bit fraction", name),
+            SolveError::Eval(e) => write!(f, "{}", e),
+            SolveError::RootIsNumber(name) => write!(f, "'{}' has no operands to compare", name),
+            SolveError::NoSolution => write!(f, "the equation has no solution"),
+            SolveError::NoIntegerSolution(x) => {
+                write!(f, "the only solution, {}, is not an integer", x)
+            }
         }
-        Exp::Mul(l, r) => {
-            return has_node(opers, l, target_node) || has_node(opers, r, target_node)
+    }
+}
+
+impl From<EvalError> for SolveError {
+    fn from(e: EvalError) -> SolveError {
+        SolveError::Eval(e)
+    }
+}
+
+/// Turns monkeys into `Linear`s in the unknown, remembering each one so
+/// shared monkeys are only expanded once.
+struct Linearizer<'a, 'b> {
+    opers: &'b HashMap<&'a str, Exp<'a>>,
+    unknown: &'b str,
+    values: HashMap<&'a str, Linear>,
+    path: Vec<&'a str>,
+}
+
+impl<'a> Linearizer<'a, '_> {
+    fn linear(&mut self, name: &str) -> Result<Linear, SolveError> {
+        if name == self.unknown {
+            return Ok(Linear {
+                a: Rational::from(1),
+                b: Rational::from(0),
+            });
         }
-        Exp::Div(l, r) => {
-            return has_node(opers, l, target_node) || has_node(opers, r, target_node)
+        if let Some(v) = self.values.get(name) {
+            return Ok(*v);
         }
+
+        let (name, exp) = lookup(self.opers, name, &self.path)?;
+        self.path.push(name);
+
+        let overflow = || SolveError::Overflow(name.to_string());
+        let value = match *exp {
+            Exp::Num(n) => Linear {
+                a: Rational::from(0),
+                b: Rational::from(n),
+            },
+            Exp::Plus(l, r) => {
+                let (l, r) = (self.linear(l)?, self.linear(r)?);
+                Linear {
+                    a: l.a.checked_add(r.a).ok_or_else(overflow)?,
+                    b: l.b.checked_add(r.b).ok_or_else(overflow)?,
+                }
+            }
+            Exp::Minus(l, r) => {
+                let (l, r) = (self.linear(l)?, self.linear(r)?);
+                Linear {
+                    a: l.a.checked_sub(r.a).ok_or_else(overflow)?,
+                    b: l.b.checked_sub(r.b).ok_or_else(overflow)?,
+                }
+            }
+            Exp::Mul(l, r) => {
+                let (l, r) = (self.linear(l)?, self.linear(r)?);
+                let (k, x) = if l.a.is_zero() {
+                    (l.b, r)
+                } else if r.a.is_zero() {
+                    (r.b, l)
+                } else {
+                    return Err(SolveError::NotLinear);
+                };
+                Linear {
+                    a: x.a.checked_mul(k).ok_or_else(overflow)?,
+                    b: x.b.checked_mul(k).ok_or_else(overflow)?,
+                }
+            }
+            Exp::Div(l, r) => {
+                let (l, r) = (self.linear(l)?, self.linear(r)?);
+                if !r.a.is_zero() {
+                    return Err(SolveError::NotLinear);
+                }
+                if r.b.is_zero() {
+                    return Err(SolveError::DivisionByZero);
+                }
+                Linear {
+                    a: l.a.checked_div(r.b).ok_or_else(overflow)?,
+                    b: l.b.checked_div(r.b).ok_or_else(overflow)?,
+                }
+            }
+        };
+
+        self.path.pop();
+        self.values.insert(name, value);
+        Ok(value)
     }
 }
 
-fn eval_with_inverse<'a>(
-    opers: &'a HashMap<&str, Exp>,
-    inv: &'a HashMap<&str, Exp>,
-    src: &str,
-) -> i64 {
-    // Search in inv first, then in opers
+/// How the left operand of the root monkey must compare to the right one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Relation {
+    Eq,
+    Lt,
+    Gt,
+}
+
+#[derive(Debug, PartialEq)]
+enum Solution {
+    Exact(i64),
+    /// Inclusive bounds, `None` meaning unbounded (or beyond what fits in an i64)
+    Range(Option<i64>, Option<i64>),
+}
 
-    let mut exp = inv.get(src);
-    if exp.is_none() {
-        exp = opers.get(src);
-        if exp.is_none() {
-            panic!("No such node: {}", src);
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Solution::Exact(x) => write!(f, "= {}", x),
+            Solution::Range(None, None) => write!(f, "can be anything"),
+            Solution::Range(Some(lo), None) => write!(f, ">= {}", lo),
+            Solution::Range(None, Some(hi)) => write!(f, "<= {}", hi),
+            Solution::Range(Some(lo), Some(hi)) => write!(f, "in {}..={}", lo, hi),
         }
     }
+}
+
+/// Finds the integer values of `unknown` for which `left <relation> right` holds,
+/// where `left` and `right` are the operands of `root` (its own operation is ignored).
+fn solve(
+    opers: &HashMap<&str, Exp>,
+    unknown: &str,
+    root: &str,
+    relation: Relation,
+) -> Result<Solution, SolveError> {
+    lookup(opers, unknown, &[])?;
+    let (root, exp) = lookup(opers, root, &[])?;
+    let (left, right) = match *exp {
+        Exp::Num(_) => return Err(SolveError::RootIsNumber(root.to_string())),
+        Exp::Plus(l, r) | Exp::Minus(l, r) | Exp::Mul(l, r) | Exp::Div(l, r) => (l, r),
+    };
+
+    let mut linearizer = Linearizer {
+        opers,
+        unknown,
+        values: HashMap::new(),
+        path: vec![root],
+    };
+    let left = linearizer.linear(left)?;
+    let right = linearizer.linear(right)?;
+
+    // a_l * x + b_l <rel> a_r * x + b_r  =>  a * x <rel> c
+    // If a is zero, this is 0 <rel> c whatever x is
+    let overflow = || SolveError::Overflow(root.to_string());
+    let a = left.a.checked_sub(right.a).ok_or_else(overflow)?;
+    let c = right.b.checked_sub(left.b).ok_or_else(overflow)?;
+    let holds_at_zero = match relation {
+        Relation::Eq => c.is_zero(),
+        Relation::Lt => c.is_positive(),
+        Relation::Gt => (-c).is_positive(),
+    };
+
+    if a.is_zero() {
+        return match holds_at_zero {
+            true => Ok(Solution::Range(None, None)),
+            false => Err(SolveError::NoSolution),
+        };
+    }
 
-    match exp.unwrap() {
-        Exp::Num(n) => *n,
-        Exp::Plus(l, r) => eval_with_inverse(&opers, &inv, l) + eval_with_inverse(&opers, &inv, r),
-        Exp::Minus(l, r) => eval_with_inverse(&opers, &inv, l) - eval_with_inverse(&opers, &inv, r),
-        Exp::Mul(l, r) => eval_with_inverse(&opers, &inv, l) * eval_with_inverse(&opers, &inv, r),
-        Exp::Div(l, r) => eval_with_inverse(&opers, &inv, l) / eval_with_inverse(&opers, &inv, r),
+    let x = c.checked_div(a).ok_or_else(overflow)?;
+    let to_i64 = |v: i128| i64::try_from(v).ok();
+
+    // Dividing by a negative a flips the inequality
+    let relation = match (relation, a.is_positive()) {
+        (Relation::Lt, false) => Relation::Gt,
+        (Relation::Gt, false) => Relation::Lt,
+        (relation, _) => relation,
+    };
+
+    match relation {
+        Relation::Eq => x
+            .to_integer()
+            .and_then(to_i64)
+            .map(Solution::Exact)
+            .ok_or(SolveError::NoIntegerSolution(x)),
+        // A bound past the end of i64 either cuts off every i64 or none of them
+        Relation::Lt => match x.ceil() - 1 {
+            hi if hi < i64::MIN as i128 => Err(SolveError::NoSolution),
+            hi => Ok(Solution::Range(None, to_i64(hi))),
+        },
+        Relation::Gt => match x.floor() + 1 {
+            lo if lo > i64::MAX as i128 => Err(SolveError::NoSolution),
+            lo => Ok(Solution::Range(to_i64(lo), None)),
+        },
     }
 }
 
 #[test]
 fn example() {
     let ans = eval(&parse(include_str!("../ex.txt")), "root");
-    assert_eq!(ans, 152);
+    assert_eq!(ans, Ok(152));
 }
 
 #[test]
 fn part_1() {
     let ans = eval(&parse(include_str!("../input.txt")), "root");
-    assert_eq!(ans, 169525884255464);
-}
-
-fn eval(opers: &HashMap<&str, Exp>, to_string: &str) -> i64 {
-    let exp = opers.get(to_string).unwrap();
-    match exp {
-        Exp::Num(n) => *n,
-        Exp::Plus(l, r) => eval(opers, l) + eval(opers, r),
-        Exp::Minus(l, r) => eval(opers, l) - eval(opers, r),
-        Exp::Mul(l, r) => eval(opers, l) * eval(opers, r),
-        Exp::Div(l, r) => eval(opers, l) / eval(opers, r),
-    }
-}
-
-fn create_inverse_map_from_node_humn<'a>(
-    opers: &'a HashMap<&str, Exp>,
-    src_node: &'a str,
-    target_node: &'a str,
-) -> HashMap<&'a str, Exp<'a>> {
-    let mut src_node = src_node;
-    let mut inverse_map = HashMap::<&str, Exp>::new();
-
-    while src_node != target_node {
-        let mut found = false;
-        for (p, exp) in opers {
-            match exp {
-                Exp::Num(_) => continue,
-                Exp::Plus(l, r) => {
-                    if *l == src_node {
-                        inverse_map.insert(src_node, Exp::Minus(p, r));
-                        println!("Since {p} = {l} + {r}, adding {} = {} - {}", src_node, p, r);
-                        src_node = p;
-                        found = true;
-                        break;
-                    } else if *r == src_node {
-                        println!("Since {p} = {l} + {r}, adding {} = {} - {}", src_node, p, l);
-                        inverse_map.insert(src_node, Exp::Minus(p, l));
-                        src_node = p;
-                        found = true;
-                        break;
-                    }
-                }
-                Exp::Minus(l, r) => {
-                    if *l == src_node {
-                        println!("Since {p} = {l} - {r}, adding {} = {} + {}", src_node, p, r);
-                        inverse_map.insert(src_node, Exp::Plus(p, r));
-                        src_node = p;
-                        found = true;
-                        break;
-                    } else if *r == src_node {
-                        println!("Since {p} = {l} - {r}, adding {} = {} - {}", src_node, l, p);
-                        inverse_map.insert(src_node, Exp::Minus(l, p));
-                        src_node = p;
-                        found = true;
-                        break;
-                    }
-                }
-                Exp::Mul(l, r) => {
-                    if *l == src_node {
-                        println!("Since {p} = {l} * {r}, adding {} = {} / {}", src_node, p, r);
-                        inverse_map.insert(src_node, Exp::Div(p, r));
-                        src_node = p;
-                        found = true;
-                        break;
-                    } else if *r == src_node {
-                        println!("Since {p} = {l} * {r}, adding {} = {} / {}", src_node, p, l);
-                        inverse_map.insert(src_node, Exp::Div(p, l));
-                        src_node = p;
-                        found = true;
-                        break;
-                    }
-                }
-                Exp::Div(l, r) => {
-                    if *l == src_node {
-                        println!("Since {p} = {l} / {r}, adding {} = {} * {}", src_node, p, r);
-                        inverse_map.insert(src_node, Exp::Mul(p, r));
-                        src_node = p;
-                        found = true;
-                        break;
-                    } else if *r == src_node {
-                        println!("Since {p} = {l} / {r}, adding {} = {} / {}", src_node, l, p);
-                        inverse_map.insert(src_node, Exp::Div(l, p));
-                        src_node = p;
-                        found = true;
-                        break;
-                    }
+    assert_eq!(ans, Ok(169525884255464));
+}
+
+#[test]
+fn eval_errors() {
+    let opers = parse("root: a + b\na: b * c\nb: 2");
+    assert_eq!(
+        eval(&opers, "root"),
+        Err(EvalError::UnknownMonkey("c".to_string()))
+    );
+
+    let opers = parse("root: a + b\na: b * c\nb: 2\nc: root - b\nhumn: 0");
+    assert_eq!(
+        eval(&opers, "root"),
+        Err(EvalError::Cycle(
+            ["root", "a", "c", "root"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        ))
+    );
+    assert!(matches!(
+        solve(&opers, "humn", "root", Relation::Eq),
+        Err(SolveError::Eval(EvalError::Cycle(_)))
+    ));
+
+    let opers = parse("root: a * a\na: 9999999999");
+    assert_eq!(
+        eval(&opers, "root"),
+        Err(EvalError::Overflow("root".to_string()))
+    );
+
+    let opers = parse("root: a / b\na: 1\nb: a - a");
+    assert_eq!(
+        eval(&opers, "root"),
+        Err(EvalError::DivisionByZero("root".to_string()))
+    );
+}
+
+#[test]
+fn eval_shared_monkeys_once() {
+    // Each monkey uses the next one twice, so without memoization this is 2^60 calls
+    let mut input = String::from("m60: 1\n");
+    for i in 0..60 {
+        input.push_str(&format!("m{}: m{} - m{}\n", i, i + 1, i + 1));
+    }
+
+    assert_eq!(eval(&parse(&input), "m0"), Ok(0));
+}
+
+#[test]
+fn solve_shared_monkeys_once() {
+    // Same graph as eval_shared_monkeys_once, with the unknown at the bottom
+    let mut input = String::from("root: m0 + b\nb: 0\nm60: humn + b\nhumn: 5\n");
+    for i in 0..60 {
+        input.push_str(&format!("m{}: m{} - m{}\n", i, i + 1, i + 1));
+    }
+
+    // m0 is always 0, so root's sides are equal for any humn
+    assert_eq!(
+        solve(&parse(&input), "humn", "root", Relation::Eq),
+        Ok(Solution::Range(None, None))
+    );
+
+    // Adding instead doubles each level, so the coefficients grow to 2^60
+    let input = input.replace(" - ", " + ");
+    assert_eq!(
+        solve(&parse(&input), "humn", "root", Relation::Eq),
+        Ok(Solution::Exact(0))
+    );
+}
+
+#[test]
+fn solve_overflow() {
+    let opers = parse(
+        "root: a + b\na: humn * k\nk: d * e\ne: d * d\nd: 9000000000000000000\nb: 3\nhumn: 0",
+    );
+    assert_eq!(
+        solve(&opers, "humn", "root", Relation::Eq),
+        Err(SolveError::Overflow("k".to_string()))
+    );
+}
+
+#[test]
+fn rational_overflow() {
+    // -2^126 * 2 is i128::MIN, which can't be negated
+    let big = Rational::checked_new(-(1 << 126), 1).unwrap();
+    assert_eq!(big.checked_mul(Rational::from(2)), None);
+    assert_eq!(big.checked_div(Rational::checked_new(-1, 2).unwrap()), None);
+    assert_eq!(big.checked_div(Rational::checked_new(1, 2).unwrap()), None);
+    assert_eq!(big.checked_sub(big), Some(Rational::from(0)));
+
+    assert_eq!(
+        Rational::checked_new(i128::MIN, i128::MIN),
+        Some(Rational::from(1))
+    );
+    assert_eq!(Rational::checked_new(i128::MIN, -1), None);
+    assert_eq!(Rational::checked_new(1, 0), None);
+    assert_eq!(Rational::checked_new(6, -4), Rational::checked_new(-3, 2));
+}
+
+#[test]
+fn example_part_2() {
+    let ans = solve(
+        &parse(include_str!("../ex.txt")),
+        "humn",
+        "root",
+        Relation::Eq,
+    );
+    assert_eq!(ans, Ok(Solution::Exact(301)));
+}
+
+#[test]
+fn part_2() {
+    let ans = solve(
+        &parse(include_str!("../input.txt")),
+        "humn",
+        "root",
+        Relation::Eq,
+    );
+    assert_eq!(ans, Ok(Solution::Exact(3247317268284)));
+}
+
+#[test]
+fn solve_any_unknown_and_root() {
+    let opers = parse(include_str!("../ex.txt"));
+
+    // pppw = cczh / lfqf, with cczh = sllz + lgvd = 4 + 2 * (humn - 3)
+    assert_eq!(
+        solve(&opers, "humn", "root", Relation::Eq),
+        Ok(Solution::Exact(301))
+    );
+    assert_eq!(
+        solve(&opers, "cczh", "root", Relation::Eq),
+        Ok(Solution::Exact(600))
+    );
+    assert_eq!(
+        solve(&opers, "humn", "cczh", Relation::Eq),
+        Ok(Solution::Exact(5))
+    );
+
+    // pppw = (4 + 2 * (humn - 3)) / 4 grows with humn, and equals sjmn = 150 at humn = 301
+    assert_eq!(
+        solve(&opers, "humn", "root", Relation::Lt),
+        Ok(Solution::Range(None, Some(300)))
+    );
+    assert_eq!(
+        solve(&opers, "humn", "root", Relation::Gt),
+        Ok(Solution::Range(Some(302), None))
+    );
+
+    // sjmn doesn't depend on humn
+    assert_eq!(
+        solve(&opers, "humn", "sjmn", Relation::Gt),
+        Ok(Solution::Range(None, None))
+    );
+    assert_eq!(
+        solve(&opers, "humn", "sjmn", Relation::Lt),
+        Err(SolveError::NoSolution)
+    );
+    assert_eq!(
+        solve(&opers, "humn", "humn", Relation::Eq),
+        Err(SolveError::RootIsNumber("humn".to_string()))
+    );
+}
+
+#[test]
+fn solve_errors() {
+    let opers = parse(include_str!("../ex.txt"));
+    for relation in [Relation::Eq, Relation::Lt, Relation::Gt] {
+        assert_eq!(
+            solve(&opers, "hmn", "root", relation),
+            Err(SolveError::Eval(EvalError::UnknownMonkey(
+                "hmn".to_string()
+            )))
+        );
+    }
+
+    // humn / 10^22 against +-3: the bound is far outside i64
+    let opers = |b: i64| {
+        format!(
+            "root: a + b\na: humn / c\nc: d * d\nd: 100000000000\nb: {}\nhumn: 0",
+            b
+        )
+    };
+    let (low, high) = (opers(-3), opers(3));
+    assert_eq!(
+        solve(&parse(&low), "humn", "root", Relation::Lt),
+        Err(SolveError::NoSolution)
+    );
+    assert_eq!(
+        solve(&parse(&high), "humn", "root", Relation::Gt),
+        Err(SolveError::NoSolution)
+    );
+    assert_eq!(
+        solve(&parse(&high), "humn", "root", Relation::Lt),
+        Ok(Solution::Range(None, None))
+    );
+    assert_eq!(
+        solve(&parse(&low), "humn", "root", Relation::Gt),
+        Ok(Solution::Range(None, None))
+    );
+    let opers = parse("root: a + b\na: humn / c\nc: 2\nb: 3\nhumn: 0");
+    assert_eq!(
+        solve(&opers, "humn", "root", Relation::Eq),
+        Ok(Solution::Exact(6))
+    );
+
+    let opers = parse("root: a + b\na: humn * c\nc: 2\nb: 3\nhumn: 0");
+    assert_eq!(
+        solve(&opers, "humn", "root", Relation::Eq),
+        Err(SolveError::NoIntegerSolution(
+            Rational::checked_new(3, 2).unwrap()
+        ))
+    );
+
+    let opers = parse("root: a + b\na: humn - humn\nb: 3\nhumn: 0");
+    assert_eq!(
+        solve(&opers, "humn", "root", Relation::Eq),
+        Err(SolveError::NoSolution)
+    );
+
+    let opers = parse("root: a + b\na: humn * humn\nb: 3\nhumn: 0");
+    assert_eq!(
+        solve(&opers, "humn", "root", Relation::Eq),
+        Err(SolveError::NotLinear)
+    );
+}
+
+fn eval(opers: &HashMap<&str, Exp>, name: &str) -> Result<i64, EvalError> {
+    let mut evaluator = Evaluator {
+        opers,
+        values: HashMap::new(),
+        path: Vec::new(),
+    };
+    evaluator.value(name)
+}
+
+/// Evaluates monkeys, remembering each value so shared monkeys are computed once.
+struct Evaluator<'a, 'b> {
+    opers: &'b HashMap<&'a str, Exp<'a>>,
+    values: HashMap<&'a str, i64>,
+    path: Vec<&'a str>,
+}
+
+impl<'a> Evaluator<'a, '_> {
+    fn value(&mut self, name: &str) -> Result<i64, EvalError> {
+        if let Some(v) = self.values.get(name) {
+            return Ok(*v);
+        }
+
+        let (name, exp) = lookup(self.opers, name, &self.path)?;
+        self.path.push(name);
+
+        let overflow = || EvalError::Overflow(name.to_string());
+        let value = match *exp {
+            Exp::Num(n) => n,
+            Exp::Plus(l, r) => self
+                .value(l)?
+                .checked_add(self.value(r)?)
+                .ok_or_else(overflow)?,
+            Exp::Minus(l, r) => self
+                .value(l)?
+                .checked_sub(self.value(r)?)
+                .ok_or_else(overflow)?,
+            Exp::Mul(l, r) => self
+                .value(l)?
+                .checked_mul(self.value(r)?)
+                .ok_or_else(overflow)?,
+            Exp::Div(l, r) => {
+                let (l, r) = (self.value(l)?, self.value(r)?);
+                if r == 0 {
+                    return Err(EvalError::DivisionByZero(name.to_string()));
                 }
+                l.checked_div(r).ok_or_else(overflow)?
             }
-        }
-        if !found {
-            panic!("Could not find node");
-        }
+        };
+
+        self.path.pop();
+        self.values.insert(name, value);
+        Ok(value)
     }
-    inverse_map
 }
 
-fn parse(input: &str) -> HashMap<&str, Exp> {
+fn parse(input: &str) -> HashMap<&str, Exp<'_>> {
     // Examples:
     // lzvm: ptgl * wvjc
     // jlbw: 5
@@ -0,0 +1,115 @@
+use std::{fmt, ops::Neg};
+
+/// An exact fraction, always kept in lowest terms with a positive denominator.
+/// The numerator is never `i128::MIN`, so negating can't overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Rational {
+    /// `None` if `den` is zero or the reduced fraction doesn't fit.
+    pub fn checked_new(num: i128, den: i128) -> Option<Rational> {
+        if den == 0 {
+            return None;
+        }
+
+        // Reduce the magnitudes, as i128::MIN has none of its own
+        let g = gcd(num.unsigned_abs(), den.unsigned_abs());
+        let abs = i128::try_from(num.unsigned_abs() / g).ok()?;
+        Some(Rational {
+            num: if (num < 0) != (den < 0) {
+                abs.checked_neg()?
+            } else {
+                abs
+            },
+            den: i128::try_from(den.unsigned_abs() / g).ok()?,
+        })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.num > 0
+    }
+
+    pub fn floor(self) -> i128 {
+        self.num.div_euclid(self.den)
+    }
+
+    pub fn ceil(self) -> i128 {
+        -(-self).floor()
+    }
+
+    /// `None` if the numerator or denominator overflows.
+    pub fn checked_add(self, o: Rational) -> Option<Rational> {
+        let num = self
+            .num
+            .checked_mul(o.den)?
+            .checked_add(o.num.checked_mul(self.den)?)?;
+        Rational::checked_new(num, self.den.checked_mul(o.den)?)
+    }
+
+    pub fn checked_sub(self, o: Rational) -> Option<Rational> {
+        self.checked_add(-o)
+    }
+
+    pub fn checked_mul(self, o: Rational) -> Option<Rational> {
+        Rational::checked_new(self.num.checked_mul(o.num)?, self.den.checked_mul(o.den)?)
+    }
+
+    /// `None` on overflow or division by zero.
+    pub fn checked_div(self, o: Rational) -> Option<Rational> {
+        if o.is_zero() {
+            return None;
+        }
+        Rational::checked_new(self.num.checked_mul(o.den)?, self.den.checked_mul(o.num)?)
+    }
+
+    /// The value as an integer, if it is one.
+    pub fn to_integer(self) -> Option<i128> {
+        match self.den {
+            1 => Some(self.num),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(n: i64) -> Rational {
+        Rational {
+            num: n as i128,
+            den: 1,
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.den {
+            1 => write!(f, "{}", self.num),
+            _ => write!(f, "{}/{}", self.num, self.den),
+        }
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        Rational {
+            num: -self.num,
+            den: self.den,
+        }
+    }
+}
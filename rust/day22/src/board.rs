@@ -0,0 +1,169 @@
+use crate::{DIRS, FACING};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tile {
+    /// Not part of the map
+    Void,
+    Open,
+    Wall,
+}
+
+/// The map as a grid of tiles, padded with `Void` to a rectangle.
+///
+/// For flat wrapping, every tile also knows the ends of the run of non-void
+/// tiles it sits in, along its row and along its column, so walking off a
+/// run is a single lookup no matter how ragged the map is.
+pub struct Board {
+    tiles: Vec<Vec<Tile>>,
+    width: i32,
+    height: i32,
+    /// First and last x of the row run each tile belongs to
+    row_runs: Vec<Vec<(i32, i32)>>,
+    /// First and last y of the column run each tile belongs to
+    col_runs: Vec<Vec<(i32, i32)>>,
+}
+
+/// Splits a line of tiles into runs of non-void ones, and records for each
+/// tile the (first, last) index of its run.
+fn runs(line: &[Tile]) -> Vec<(i32, i32)> {
+    let mut result = vec![(0, -1); line.len()];
+    let mut start = 0;
+
+    while start < line.len() {
+        if line[start] == Tile::Void {
+            start += 1;
+            continue;
+        }
+        let end = (start..line.len())
+            .take_while(|i| line[*i] != Tile::Void)
+            .last()
+            .unwrap();
+        for r in result.iter_mut().take(end + 1).skip(start) {
+            *r = (start as i32, end as i32);
+        }
+        start = end + 1;
+    }
+
+    result
+}
+
+impl Board {
+    pub fn parse(map_lines: &[&str]) -> Board {
+        let width = map_lines.iter().map(|l| l.len()).max().unwrap_or(0);
+
+        let tiles: Vec<Vec<Tile>> = map_lines
+            .iter()
+            .map(|line| {
+                let mut row: Vec<Tile> = line
+                    .chars()
+                    .map(|c| match c {
+                        '.' => Tile::Open,
+                        '#' => Tile::Wall,
+                        _ => Tile::Void,
+                    })
+                    .collect();
+                row.resize(width, Tile::Void);
+                row
+            })
+            .collect();
+
+        let row_runs = tiles.iter().map(|row| runs(row)).collect();
+
+        let columns: Vec<Vec<(i32, i32)>> = (0..width)
+            .map(|x| runs(&tiles.iter().map(|row| row[x]).collect::<Vec<_>>()))
+            .collect();
+        let col_runs = (0..tiles.len())
+            .map(|y| columns.iter().map(|col| col[y]).collect())
+            .collect();
+
+        Board {
+            width: width as i32,
+            height: tiles.len() as i32,
+            tiles,
+            row_runs,
+            col_runs,
+        }
+    }
+
+    pub fn tile(&self, (x, y): (i32, i32)) -> Tile {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return Tile::Void;
+        }
+        self.tiles[y as usize][x as usize]
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // Each tile of `trail` shows the facing last seen there
+    pub fn render(&self, trail: &HashMap<(i32, i32), usize>) -> String {
+        let mut out = String::new();
+
+        for y in 0..self.height {
+            let line: String = (0..self.width)
+                .map(|x| match (trail.get(&(x, y)), self.tile((x, y))) {
+                    (Some(dir), _) => FACING[*dir],
+                    (None, Tile::Open) => '.',
+                    (None, Tile::Wall) => '#',
+                    (None, Tile::Void) => ' ',
+                })
+                .collect();
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Every non-void tile, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (0..self.height)
+            .flat_map(move |y| (0..self.width).map(move |x| (x, y)))
+            .filter(|pos| self.tile(*pos) != Tile::Void)
+    }
+
+    /// The tile one step from `pos` in direction `dir`, coming back in at the
+    /// other end of the row or column when walking off the map.
+    pub fn flat_step(&self, pos: (i32, i32), dir: usize) -> (i32, i32) {
+        let (x, y) = pos;
+        let (dx, dy) = DIRS[dir];
+        let next = (x + dx, y + dy);
+        if self.tile(next) != Tile::Void {
+            return next;
+        }
+
+        let (first_x, last_x) = self.row_runs[y as usize][x as usize];
+        let (first_y, last_y) = self.col_runs[y as usize][x as usize];
+        match dir {
+            0 => (first_x, y),
+            1 => (x, first_y),
+            2 => (last_x, y),
+            _ => (x, last_y),
+        }
+    }
+}
+
+#[test]
+fn flat_step_on_ragged_map() {
+    let board = Board::parse(&["  ..#", "....", ".. ..", "   ."]);
+
+    assert_eq!(board.tile((0, 0)), Tile::Void);
+    assert_eq!(board.tile((4, 0)), Tile::Wall);
+    assert_eq!(board.tile((9, 9)), Tile::Void);
+
+    // Rows wrap at their own ends
+    assert_eq!(board.flat_step((4, 0), 0), (2, 0));
+    assert_eq!(board.flat_step((0, 1), 2), (3, 1));
+
+    // Interior gaps split a row or column into separate runs
+    assert_eq!(board.flat_step((1, 2), 0), (0, 2));
+    assert_eq!(board.flat_step((3, 2), 2), (4, 2));
+    assert_eq!(board.flat_step((4, 2), 3), (4, 2));
+    assert_eq!(board.flat_step((4, 0), 1), (4, 0));
+
+    // Columns only span the rows that reach them
+    assert_eq!(board.flat_step((3, 3), 1), (3, 0));
+    assert_eq!(board.flat_step((3, 0), 3), (3, 3));
+}
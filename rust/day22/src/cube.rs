@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{in_bounds, State, DIRS};
+
+type V3 = [i32; 3];
+
+fn neg(v: V3) -> V3 {
+    [-v[0], -v[1], -v[2]]
+}
+
+fn dot(a: V3, b: V3) -> i32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn add(a: V3, b: V3) -> V3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(v: V3, k: i32) -> V3 {
+    [v[0] * k, v[1] * k, v[2] * k]
+}
+
+/// Where a face of the net ends up once the net is folded into a cube:
+/// its outward normal, and the 3D directions of the map's right (+x) and down (+y).
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    normal: V3,
+    right: V3,
+    down: V3,
+}
+
+impl Frame {
+    /// The 3D direction of one of the map's `DIRS` on this face.
+    fn heading(&self, dir: usize) -> V3 {
+        match dir {
+            0 => self.right,
+            1 => self.down,
+            2 => neg(self.right),
+            _ => neg(self.down),
+        }
+    }
+
+    /// The frame of the face we reach by walking off this one in map direction `dir`.
+    fn fold(&self, dir: usize) -> Frame {
+        let Frame {
+            normal,
+            right,
+            down,
+        } = *self;
+
+        // The neighbour's normal is where we were heading, and walking on
+        // over the edge now goes "into" the cube, away from our old normal
+        match dir {
+            0 => Frame {
+                normal: right,
+                right: neg(normal),
+                down,
+            },
+            1 => Frame {
+                normal: down,
+                right,
+                down: neg(normal),
+            },
+            2 => Frame {
+                normal: neg(right),
+                right: normal,
+                down,
+            },
+            _ => Frame {
+                normal: neg(down),
+                right,
+                down: normal,
+            },
+        }
+    }
+}
+
+/// The map folded into a cube, so walking off the edge of a face can be
+/// mapped to the adjacent face of the cube.
+pub struct Cube {
+    size: i32,
+    /// Frame of each face, keyed by its (column, row) in the net
+    faces: HashMap<(i32, i32), Frame>,
+}
+
+impl Cube {
+    /// Detects the face size and the net layout from the map's bounds, and
+    /// works out how the faces fold up. Works for any of the cube's nets.
+    pub fn fold(state: &State) -> Result<Cube, String> {
+        let tiles = state.board.positions().count() as i32;
+        let size = ((tiles / 6) as f64).sqrt().round() as i32;
+        if size == 0 || size * size * 6 != tiles {
+            return Err(format!("{} tiles can't be folded into a cube", tiles));
+        }
+
+        let is_face = |(fx, fy): (i32, i32)| in_bounds(state, (fx * size, fy * size));
+
+        let rows = state.board.height() / size;
+        let first = state
+            .board
+            .positions()
+            .map(|(x, y)| (x / size, y / size))
+            .find(|f| f.1 == 0 && is_face(*f))
+            .ok_or("The map's first row doesn't start a face")?;
+
+        // Walk the net, folding each face relative to the one we came from
+        let mut faces = HashMap::new();
+        faces.insert(
+            first,
+            Frame {
+                normal: [0, 0, -1],
+                right: [1, 0, 0],
+                down: [0, 1, 0],
+            },
+        );
+        let mut queue = VecDeque::from([first]);
+
+        while let Some(face) = queue.pop_front() {
+            let frame = faces[&face];
+            for (dir, (dx, dy)) in DIRS.iter().enumerate() {
+                let next = (face.0 + dx, face.1 + dy);
+                if next.1 < 0 || next.1 >= rows || faces.contains_key(&next) || !is_face(next) {
+                    continue;
+                }
+                faces.insert(next, frame.fold(dir));
+                queue.push_back(next);
+            }
+        }
+
+        // Six faces of the right size only cover every tile if the net is
+        // whole, and they only make a cube if no two fold onto the same side
+        let covered = |(x, y): (i32, i32)| faces.contains_key(&(x / size, y / size));
+        let sides: HashSet<V3> = faces.values().map(|f| f.normal).collect();
+        if faces.len() != 6 || sides.len() != 6 || !state.board.positions().all(covered) {
+            return Err("The map isn't a cube net".to_string());
+        }
+        Ok(Cube { size, faces })
+    }
+
+    /// The tile and direction we end up on when walking off the map from
+    /// `pos` in direction `dir`, going around the cube's edge.
+    pub fn wrap(&self, pos: (i32, i32), dir: usize) -> ((i32, i32), usize) {
+        let n = self.size;
+        let face = (pos.0.div_euclid(n), pos.1.div_euclid(n));
+        let from = self.faces[&face];
+        let heading = from.heading(dir);
+
+        // Tile centres in 3D, on a cube spanning -n..n (doubled so they're integers)
+        let (i, j) = (pos.0 - face.0 * n, pos.1 - face.1 * n);
+        let p = add(
+            scale(from.normal, n),
+            add(
+                scale(from.right, 2 * i + 1 - n),
+                scale(from.down, 2 * j + 1 - n),
+            ),
+        );
+
+        // Over the edge: one half step further along the heading, one half step into the cube
+        let p = add(p, add(heading, neg(from.normal)));
+
+        let (to_face, to) = self
+            .faces
+            .iter()
+            .find(|(_, f)| f.normal == heading)
+            .unwrap();
+
+        let (i, j) = (
+            (dot(p, to.right) + n - 1) / 2,
+            (dot(p, to.down) + n - 1) / 2,
+        );
+        let new_dir = (0..4).find(|d| to.heading(*d) == neg(from.normal)).unwrap();
+
+        ((to_face.0 * n + i, to_face.1 * n + j), new_dir)
+    }
+}
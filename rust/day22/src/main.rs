@@ -1,9 +1,162 @@
-use std::collections::HashSet;
+mod board;
+mod cube;
+
+use board::{Board, Tile};
+use cube::Cube;
+use std::collections::HashMap;
 
 fn main() {
+    // Usage:
+    //   day22 [input]
+    //   day22 trace <input> <flat|cube> [instructions]   draw the path, optionally
+    //                                                    stopping after N instructions
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|a| a.as_str()) == Some("trace") {
+        let input = std::fs::read_to_string(&args[1]).unwrap();
+        let mode = match args[2].as_str() {
+            "flat" => WrapMode::Flat,
+            "cube" => WrapMode::Cube,
+            other => panic!("Unknown wrap mode '{}', expected flat or cube", other),
+        };
+        let limit = args.get(3).map(|n| n.parse().unwrap());
+
+        let mut state = parse(&input);
+        let trail = match walk(&mut state, mode, limit) {
+            Ok(trail) => trail,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        print!("{}", state.board.render(&trail));
+        println!(
+            "After {} instructions: row {}, column {}, facing {}",
+            limit.unwrap_or(state.path.len()).min(state.path.len()),
+            state.pos.1 + 1,
+            state.pos.0 + 1,
+            FACING[state.dir]
+        );
+        println!("Password: {}", password(&state));
+        return;
+    }
+
+    let file = args.first().map(|a| a.as_str()).unwrap_or("input.txt");
+    let input = std::fs::read_to_string(file).unwrap();
+
+    let part_1 = execute_instructions(parse(&input), WrapMode::Flat).unwrap();
+    println!("Part 1: {}", part_1);
+
+    match execute_instructions(parse(&input), WrapMode::Cube) {
+        Ok(part_2) => println!("Part 2: {}", part_2),
+        Err(e) => println!("Part 2 doesn't apply: {}", e),
+    }
+}
+
+#[test]
+fn example() {
+    let input = include_str!("../ex.txt");
+    assert_eq!(execute_instructions(parse(input), WrapMode::Flat), Ok(6032));
+    assert_eq!(execute_instructions(parse(input), WrapMode::Cube), Ok(5031));
+}
+
+#[test]
+fn part_1() {
     let input = include_str!("../input.txt");
-    let state = parse(input);
-    execute_instructions(state);
+    assert_eq!(
+        execute_instructions(parse(input), WrapMode::Flat),
+        Ok(11464)
+    );
+}
+
+#[test]
+fn example_trace() {
+    let mut state = parse(include_str!("../ex.txt"));
+    let trail = walk(&mut state, WrapMode::Flat, None).unwrap();
+
+    assert_eq!(
+        state.board.render(&trail),
+        [
+            "        >>v#",
+            "        .#v.",
+            "        #.v.",
+            "        ..v.",
+            "...#...v..v#",
+            ">>>v...>#.>>",
+            "..#v...#....",
+            "...>>>>v..#.",
+            "        ...#....",
+            "        .....#..",
+            "        .#......",
+            "        ......#.",
+            "",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn example_replay() {
+    // 10R5: ten steps right (stopped by the wall after two), turn, five steps down
+    let mut state = parse(include_str!("../ex.txt"));
+    walk(&mut state, WrapMode::Flat, Some(2)).unwrap();
+    assert_eq!((state.pos, state.dir), ((10, 0), 1));
+
+    let mut state = parse(include_str!("../ex.txt"));
+    walk(&mut state, WrapMode::Flat, Some(3)).unwrap();
+    assert_eq!((state.pos, state.dir), ((10, 5), 1));
+}
+
+#[test]
+fn not_a_cube_net() {
+    // Six faces' worth of tiles, but laid out in a 3x2 block
+    let input = ["......", "......", "......", "......", "", "3"].join("\n");
+    assert_eq!(
+        execute_instructions(parse(&input), WrapMode::Flat),
+        Ok(1016)
+    );
+    assert_eq!(
+        execute_instructions(parse(&input), WrapMode::Cube),
+        Err("The map isn't a cube net".to_string())
+    );
+
+    let input = [".....", "", "3"].join("\n");
+    assert_eq!(
+        execute_instructions(parse(&input), WrapMode::Cube),
+        Err("5 tiles can't be folded into a cube".to_string())
+    );
+}
+
+#[test]
+fn cube_wrapping_is_reversible() {
+    // Walking off any edge and straight back must land where we started,
+    // for both the example's net and the input's
+    for input in [include_str!("../ex.txt"), include_str!("../input.txt")] {
+        let state = parse(input);
+        let cube = Cube::fold(&state).unwrap();
+
+        for pos in state.board.positions() {
+            for (dir, (dx, dy)) in DIRS.iter().enumerate() {
+                if in_bounds(&state, (pos.0 + dx, pos.1 + dy)) {
+                    continue;
+                }
+
+                let (wrapped, new_dir) = cube.wrap(pos, dir);
+                assert!(in_bounds(&state, wrapped));
+                let (back, back_dir) = cube.wrap(wrapped, (new_dir + 2) % 4);
+                assert_eq!((back, (back_dir + 2) % 4), (pos, dir));
+            }
+        }
+    }
+}
+
+/// What happens when walking off the edge of the map.
+#[derive(Debug, Clone, Copy)]
+enum WrapMode {
+    /// Come back in on the opposite side of the same row or column
+    Flat,
+    /// Fold the map into a cube and continue on the adjacent face
+    Cube,
 }
 
 #[derive(Debug)]
@@ -14,32 +167,19 @@ enum Instruction {
 }
 
 struct State {
-    bounds: Vec<(i32, i32)>,
-    obstacles: HashSet<(i32, i32)>,
+    board: Board,
     pos: (i32, i32),
     dir: usize,
     path: Vec<Instruction>,
 }
 
 const DIRS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const FACING: [char; 4] = ['>', 'v', '<', '^'];
 
 fn parse(input: &str) -> State {
     let map_lines: Vec<&str> = input.lines().take_while(|l| !l.is_empty()).collect();
 
-    let mut bounds: Vec<(i32, i32)> = vec![];
-    let mut obstacles = HashSet::new();
-    for (line_num, line) in map_lines.iter().enumerate() {
-        let min_c = line.chars().position(|c| c != ' ').unwrap();
-        let max_c = line.len();
-
-        for (i, c) in line.chars().enumerate() {
-            if c == '#' {
-                obstacles.insert((i as i32, line_num as i32));
-            }
-        }
-
-        bounds.push((min_c as i32, max_c as i32));
-    }
+    let board = Board::parse(&map_lines);
 
     // Parse the path with a regex
     let mut path: Vec<Instruction> = Vec::new();
@@ -65,47 +205,61 @@ fn parse(input: &str) -> State {
     }
 
     State {
-        bounds,
-        obstacles,
+        board,
         pos,
         dir: 0,
         path,
     }
 }
 
-fn execute_instructions(state: State) {
+fn execute_instructions(state: State, mode: WrapMode) -> Result<i32, String> {
     let mut state = state;
-    for instruction in &state.path {
+    walk(&mut state, mode, None)?;
+
+    println!("Final position: {:?}", state.pos);
+    println!("Final direction: {:?}", state.dir);
+
+    let password = password(&state);
+    println!("Password: {}", password);
+    Ok(password)
+}
+
+/// Follows the first `limit` instructions (all of them if `None`), and returns
+/// the facing we last had on every tile we've been on. Fails if the map
+/// can't be folded into a cube for `WrapMode::Cube`.
+fn walk(
+    state: &mut State,
+    mode: WrapMode,
+    limit: Option<usize>,
+) -> Result<HashMap<(i32, i32), usize>, String> {
+    let cube = match mode {
+        WrapMode::Flat => None,
+        WrapMode::Cube => Some(Cube::fold(state)?),
+    };
+
+    let mut trail = HashMap::from([(state.pos, state.dir)]);
+    let limit = limit.unwrap_or(state.path.len());
+
+    for instruction in state.path.iter().take(limit) {
         match instruction {
             Instruction::Walk(n) => {
-                println!(
-                    "Walking {:?} steps in dir {}, from {:?}",
-                    n, state.dir, state.pos
-                );
                 for _ in 0..*n {
                     let (dx, dy) = DIRS[state.dir];
-                    let mut new_pos = (state.pos.0 + dx, state.pos.1 + dy);
-
-                    // If outside the board, walk in the opposite direction until we hit a wall
-                    if !in_bounds(&state, new_pos) {
-                        let (dx, dy) = DIRS[(state.dir + 2) % 4];
-                        let mut wrapped = state.pos;
-                        while in_bounds(&state, wrapped) {
-                            wrapped = (wrapped.0 + dx, wrapped.1 + dy);
+                    let stepped = (state.pos.0 + dx, state.pos.1 + dy);
+
+                    let (new_pos, new_dir) = match &cube {
+                        Some(cube) if state.board.tile(stepped) == Tile::Void => {
+                            cube.wrap(state.pos, state.dir)
                         }
-                        new_pos = (wrapped.0 - dx, wrapped.1 - dy);
-                        println!("Wrapped around from {:?} to {:?}", state.pos, new_pos)
-                    }
+                        _ => (state.board.flat_step(state.pos, state.dir), state.dir),
+                    };
 
-                    if state.obstacles.contains(&new_pos) {
-                        println!("Hit an obstacle at {:?}", new_pos);
+                    if state.board.tile(new_pos) == Tile::Wall {
                         break;
                     }
-                    println!(
-                        "[{}, {}] -> [{}, {}]",
-                        state.pos.1, state.pos.0, new_pos.1, new_pos.0
-                    );
                     state.pos = new_pos;
+                    state.dir = new_dir;
+                    trail.insert(state.pos, state.dir);
                 }
             }
             Instruction::TurnLeft => {
@@ -115,24 +269,19 @@ fn execute_instructions(state: State) {
                 state.dir = (state.dir + 1) % 4;
             }
         }
+        trail.insert(state.pos, state.dir);
     }
-    println!("Final position: {:?}", state.pos);
-    println!("Final direction: {:?}", state.dir);
 
+    Ok(trail)
+}
+
+fn password(state: &State) -> i32 {
     let final_row = state.pos.1 + 1;
     let final_col = state.pos.0 + 1;
     let final_dir = state.dir;
-    let password = 1000 * final_row + 4 * final_col + final_dir as i32;
-    println!("Password: {}", password);
+    1000 * final_row + 4 * final_col + final_dir as i32
 }
 
 fn in_bounds(state: &State, pos: (i32, i32)) -> bool {
-    let (x, y) = pos;
-
-    if y < 0 || y >= state.bounds.len() as i32 {
-        return false;
-    }
-
-    let (min_x, max_x) = state.bounds[y as usize];
-    x >= min_x && x < max_x
+    state.board.tile(pos) != Tile::Void
 }
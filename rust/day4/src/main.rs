@@ -1,3 +1,4 @@
+use interval_set::IntervalSet;
 use std::{fs::File, io::Read};
 
 fn main() {
@@ -24,19 +25,23 @@ fn main() {
     for line in contents.lines() {
         let nums = line
             .split(',')
-            .map(|x| {
+            .flat_map(|x| {
                 x.split('-')
-                    .map(|x| x.parse::<u32>().unwrap())
-                    .collect::<Vec<u32>>()
+                    .map(|x| x.parse::<i64>().unwrap())
+                    .collect::<Vec<i64>>()
             })
-            .flatten()
-            .collect::<Vec<u32>>();
+            .collect::<Vec<i64>>();
 
         // unpack the four numbers in nums
         let (a, b, c, d) = (nums[0], nums[1], nums[2], nums[3]);
 
+        let mut first = IntervalSet::new();
+        first.insert(a, b);
+        let mut second = IntervalSet::new();
+        second.insert(c, d);
+
         // check if the a,b interval is fully contained within c, d or viceversa
-        if (a >= c && b <= d) || (c >= a && d <= b) {
+        if second.contains_interval(a, b) || first.contains_interval(c, d) {
             println!("{}-{} is fully contained within {}-{}", a, b, c, d);
             fully_contained_count += 1;
         } else {
@@ -44,7 +49,7 @@ fn main() {
         }
 
         // check for any kind of overlap
-        if (a >= c && a <= d) || (b >= c && b <= d) || (c >= a && c <= b) || (d >= a && d <= b) {
+        if first.overlaps(c, d) {
             println!("{}-{} overlaps with {}-{}", a, b, c, d);
             partial_overlap += 1;
         } else {
@@ -0,0 +1,274 @@
+use std::cmp::{max, min};
+
+/// A set of integers stored as sorted, disjoint, inclusive `(lo, hi)` intervals.
+///
+/// Intervals that overlap or touch (e.g. `(1, 3)` and `(4, 6)`) are always
+/// merged, so two sets with the same points have the same representation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    intervals: Vec<(i64, i64)>,
+}
+
+impl IntervalSet {
+    pub fn new() -> IntervalSet {
+        IntervalSet {
+            intervals: Vec::new(),
+        }
+    }
+
+    pub fn intervals(&self) -> &[(i64, i64)] {
+        &self.intervals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Adds every point in `lo..=hi`. Empty intervals (`lo > hi`) are ignored.
+    pub fn insert(&mut self, lo: i64, hi: i64) {
+        if lo > hi {
+            return;
+        }
+
+        // First interval that ends at or after lo - 1, i.e. that could touch the new one
+        let start = self
+            .intervals
+            .partition_point(|&(_, b)| b < lo.saturating_sub(1));
+        let (mut lo, mut hi) = (lo, hi);
+        let mut end = start;
+
+        while end < self.intervals.len() && self.intervals[end].0 <= hi.saturating_add(1) {
+            lo = min(lo, self.intervals[end].0);
+            hi = max(hi, self.intervals[end].1);
+            end += 1;
+        }
+
+        self.intervals.splice(start..end, [(lo, hi)]);
+    }
+
+    /// Removes a single point, splitting the interval that contains it if needed.
+    pub fn remove(&mut self, x: i64) {
+        let i = self.intervals.partition_point(|&(_, b)| b < x);
+        if i == self.intervals.len() || self.intervals[i].0 > x {
+            return;
+        }
+
+        // x - 1 and x + 1 can only overflow when that side is empty anyway
+        let (a, b) = self.intervals[i];
+        let mut pieces = Vec::new();
+        if a < x {
+            pieces.push((a, x - 1));
+        }
+        if x < b {
+            pieces.push((x + 1, b));
+        }
+        self.intervals.splice(i..=i, pieces);
+    }
+
+    pub fn contains(&self, x: i64) -> bool {
+        let i = self.intervals.partition_point(|&(_, b)| b < x);
+        i < self.intervals.len() && self.intervals[i].0 <= x
+    }
+
+    /// True if every point in `lo..=hi` is in the set.
+    pub fn contains_interval(&self, lo: i64, hi: i64) -> bool {
+        if lo > hi {
+            return true;
+        }
+        let i = self.intervals.partition_point(|&(_, b)| b < lo);
+        i < self.intervals.len() && self.intervals[i].0 <= lo && hi <= self.intervals[i].1
+    }
+
+    /// True if any point in `lo..=hi` is in the set.
+    pub fn overlaps(&self, lo: i64, hi: i64) -> bool {
+        if lo > hi {
+            return false;
+        }
+        let i = self.intervals.partition_point(|&(_, b)| b < lo);
+        i < self.intervals.len() && self.intervals[i].0 <= hi
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        let mut result = self.clone();
+        for &(lo, hi) in &other.intervals {
+            result.insert(lo, hi);
+        }
+        result
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = self.intervals[i];
+            let (c, d) = other.intervals[j];
+
+            let (lo, hi) = (max(a, c), min(b, d));
+            if lo <= hi {
+                intervals.push((lo, hi));
+            }
+
+            // Advance whichever interval ends first
+            if b < d {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+
+    /// Returns the part of the set that lies within `lo..=hi`.
+    pub fn clamp(&self, lo: i64, hi: i64) -> IntervalSet {
+        let mut range = IntervalSet::new();
+        range.insert(lo, hi);
+        self.intersection(&range)
+    }
+
+    /// Total number of points in the set, saturating at `i64::MAX`.
+    pub fn len(&self) -> i64 {
+        let len: i128 = self
+            .intervals
+            .iter()
+            .map(|&(lo, hi)| hi as i128 - lo as i128 + 1)
+            .sum();
+        i64::try_from(len).unwrap_or(i64::MAX)
+    }
+
+    /// The holes between consecutive intervals, as inclusive `(lo, hi)` pairs.
+    pub fn gaps(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        // Intervals never touch, so there's room for the +1 and -1
+        self.intervals.windows(2).map(|w| (w[0].1 + 1, w[1].0 - 1))
+    }
+
+    /// The points of `lo..=hi` that are not in the set, as inclusive `(lo, hi)` pairs.
+    pub fn gaps_within(&self, lo: i64, hi: i64) -> Vec<(i64, i64)> {
+        let mut gaps = Vec::new();
+        let mut next = lo;
+
+        for &(a, b) in &self.clamp(lo, hi).intervals {
+            if a > next {
+                gaps.push((next, a - 1));
+            }
+            match b.checked_add(1) {
+                Some(n) => next = n,
+                None => return gaps,
+            }
+        }
+        if next <= hi {
+            gaps.push((next, hi));
+        }
+
+        gaps
+    }
+}
+
+impl FromIterator<(i64, i64)> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = (i64, i64)>>(iter: T) -> Self {
+        let mut set = IntervalSet::new();
+        for (lo, hi) in iter {
+            set.insert(lo, hi);
+        }
+        set
+    }
+}
+
+#[test]
+fn test_insert_merges_overlapping_and_adjacent() {
+    let set: IntervalSet = vec![(7, 8), (1, 3), (2, 4), (7, 10), (12, 13), (8, 9)]
+        .into_iter()
+        .collect();
+    assert_eq!(set.intervals(), &[(1, 4), (7, 10), (12, 13)]);
+
+    let set: IntervalSet = vec![(1, 3), (4, 6)].into_iter().collect();
+    assert_eq!(set.intervals(), &[(1, 6)]);
+
+    let mut set: IntervalSet = vec![(1, 2), (5, 6), (9, 10)].into_iter().collect();
+    set.insert(3, 8);
+    assert_eq!(set.intervals(), &[(1, 10)]);
+}
+
+#[test]
+fn test_empty_set() {
+    let set = IntervalSet::new();
+    assert!(set.is_empty());
+    assert_eq!(set.len(), 0);
+    assert_eq!(set.gaps().count(), 0);
+    assert_eq!(set.gaps_within(0, 3), vec![(0, 3)]);
+    assert!(!set.contains(0));
+}
+
+#[test]
+fn test_remove() {
+    let mut set: IntervalSet = vec![(1, 5)].into_iter().collect();
+    set.remove(3);
+    assert_eq!(set.intervals(), &[(1, 2), (4, 5)]);
+    set.remove(1);
+    set.remove(7);
+    assert_eq!(set.intervals(), &[(2, 2), (4, 5)]);
+    set.remove(2);
+    assert_eq!(set.intervals(), &[(4, 5)]);
+}
+
+#[test]
+fn test_union_and_intersection() {
+    let a: IntervalSet = vec![(0, 4), (10, 14)].into_iter().collect();
+    let b: IntervalSet = vec![(3, 11), (20, 21)].into_iter().collect();
+
+    assert_eq!(a.union(&b).intervals(), &[(0, 14), (20, 21)]);
+    assert_eq!(a.intersection(&b).intervals(), &[(3, 4), (10, 11)]);
+}
+
+#[test]
+fn test_clamp_len_and_gaps() {
+    let set: IntervalSet = vec![(-5, 2), (4, 6), (10, 30)].into_iter().collect();
+
+    assert_eq!(set.len(), 8 + 3 + 21);
+    assert_eq!(set.clamp(0, 20).intervals(), &[(0, 2), (4, 6), (10, 20)]);
+    assert_eq!(set.gaps().collect::<Vec<_>>(), vec![(3, 3), (7, 9)]);
+    assert_eq!(set.gaps_within(-10, 8), vec![(-10, -6), (3, 3), (7, 8)]);
+}
+
+#[test]
+fn test_containment() {
+    let set: IntervalSet = vec![(2, 4), (8, 9)].into_iter().collect();
+
+    assert!(set.contains(3));
+    assert!(!set.contains(5));
+    assert!(set.contains_interval(2, 4));
+    assert!(!set.contains_interval(3, 8));
+    assert!(set.overlaps(4, 6));
+    assert!(!set.overlaps(5, 7));
+}
+
+#[test]
+fn test_i64_extremes() {
+    let mut set = IntervalSet::new();
+    set.insert(0, i64::MAX);
+    set.insert(i64::MIN, -5);
+    assert_eq!(set.intervals(), &[(i64::MIN, -5), (0, i64::MAX)]);
+    assert_eq!(set.gaps().collect::<Vec<_>>(), vec![(-4, -1)]);
+    assert_eq!(set.gaps_within(i64::MIN, i64::MAX), vec![(-4, -1)]);
+    assert_eq!(set.len(), i64::MAX);
+
+    set.remove(i64::MIN);
+    set.remove(i64::MAX);
+    assert_eq!(set.intervals(), &[(i64::MIN + 1, -5), (0, i64::MAX - 1)]);
+
+    set.insert(-4, -1);
+    set.insert(i64::MIN, i64::MIN);
+    set.insert(i64::MAX, i64::MAX);
+    assert_eq!(set.intervals(), &[(i64::MIN, i64::MAX)]);
+    assert!(set.contains_interval(i64::MIN, i64::MAX));
+    assert_eq!(set.gaps_within(i64::MIN, i64::MAX), vec![]);
+
+    let mut set: IntervalSet = vec![(i64::MIN, i64::MIN), (i64::MAX, i64::MAX)]
+        .into_iter()
+        .collect();
+    assert_eq!(set.len(), 2);
+    set.remove(i64::MIN);
+    set.remove(i64::MAX);
+    assert!(set.is_empty());
+}